@@ -0,0 +1,195 @@
+use vulkano::image::view::ImageView;
+use vulkano::image::ImmutableImage;
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::scene::Mesh;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+// A cached resource and the generation of its path it was loaded under.
+pub struct Loaded<T> {
+    pub value: T,
+    pub generation: u64,
+}
+
+struct Slots<T> {
+    values: HashMap<PathBuf, T>,
+    // bumped by every reload of a path; a load that started under an older
+    // generation finished too late and is thrown away
+    generations: HashMap<PathBuf, u64>,
+}
+
+impl<T> Default for Slots<T> {
+    fn default() -> Self {
+        Slots {
+            values: HashMap::new(),
+            generations: HashMap::new(),
+        }
+    }
+}
+
+// Loaded textures and meshes keyed by the path they came from, so asking for the
+// same file twice hands back the GPU resource that already exists. Safe to share
+// between loader threads behind an `Arc`.
+#[derive(Default)]
+pub struct ResourceCache {
+    textures: Mutex<Slots<Arc<ImageView<ImmutableImage>>>>,
+    meshes: Mutex<Slots<Mesh>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl ResourceCache {
+    pub fn new() -> Self {
+        ResourceCache::default()
+    }
+
+    // Returns the cached texture for `path`, running `load` on a miss. The lock is
+    // only held to look up and insert, never while loading, so an upload on a loader
+    // thread doesn't stall the main thread clearing. Two threads missing the same
+    // file at once both load it and the first to finish wins. A failed load caches
+    // nothing, and one overtaken by a `reload_texture` gives `None`.
+    pub fn texture<E>(
+        &self,
+        path: &Path,
+        load: impl FnOnce() -> Result<Arc<ImageView<ImmutableImage>>, E>,
+    ) -> Result<Option<Loaded<Arc<ImageView<ImmutableImage>>>>, E> {
+        self.get_or_try_insert(&self.textures, path, false, load)
+    }
+
+    // Loads `path` again, for when the file changed, and replaces whatever is cached
+    // for it. Of several reloads of the same path in flight, only the one started
+    // last is kept, the others give `None` however their loads finish.
+    pub fn reload_texture<E>(
+        &self,
+        path: &Path,
+        load: impl FnOnce() -> Result<Arc<ImageView<ImmutableImage>>, E>,
+    ) -> Result<Option<Loaded<Arc<ImageView<ImmutableImage>>>>, E> {
+        self.get_or_try_insert(&self.textures, path, true, load)
+    }
+
+    pub fn mesh(&self, path: &Path, load: impl FnOnce() -> Mesh) -> Mesh {
+        // meshes are never reloaded, so no load of one is ever overtaken
+        self.get_or_try_insert(&self.meshes, path, false, || Ok::<_, Infallible>(load()))
+            .unwrap_or_else(|never| match never {})
+            .unwrap()
+            .value
+    }
+
+    // Forgets every cached resource. Anything still holding one keeps it alive; the
+    // next request for its path loads it again.
+    pub fn clear(&self) {
+        self.textures.lock().unwrap().values.clear();
+        self.meshes.lock().unwrap().values.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn get_or_try_insert<T: Clone, E>(
+        &self,
+        slots: &Mutex<Slots<T>>,
+        path: &Path,
+        reload: bool,
+        load: impl FnOnce() -> Result<T, E>,
+    ) -> Result<Option<Loaded<T>>, E> {
+        let generation = {
+            let mut slots = slots.lock().unwrap();
+            let generation = slots.generations.get(path).copied().unwrap_or(0);
+
+            if !reload {
+                if let Some(value) = slots.values.get(path) {
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    return Ok(Some(Loaded { value: value.clone(), generation }));
+                }
+            }
+
+            let generation = if reload { generation + 1 } else { generation };
+            slots.generations.insert(path.to_path_buf(), generation);
+            generation
+        };
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let value = load()?;
+
+        let mut slots = slots.lock().unwrap();
+        if slots.generations[path] != generation {
+            return Ok(None);
+        }
+        let value = if reload {
+            slots.values.insert(path.to_path_buf(), value.clone());
+            value
+        } else {
+            slots.values.entry(path.to_path_buf()).or_insert(value).clone()
+        };
+
+        Ok(Some(Loaded { value, generation }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn overlapping_reloads_keep_the_latest() {
+        let cache = ResourceCache::new();
+        let slots = Mutex::new(Slots::<u32>::default());
+        let path = Path::new("volume.raw");
+        let (finish_first, first_may_finish) = mpsc::channel();
+        let (first_started, wait_for_first) = mpsc::channel();
+
+        thread::scope(|scope| {
+            let (cache, slots) = (&cache, &slots);
+            // the first reload reads the old file and is still loading when the second
+            // one starts and finishes
+            let first = scope.spawn(move || {
+                cache.get_or_try_insert(slots, path, true, || {
+                    first_started.send(()).unwrap();
+                    first_may_finish.recv().unwrap();
+                    Ok::<_, Infallible>(1)
+                })
+            });
+            wait_for_first.recv().unwrap();
+
+            let second = cache.get_or_try_insert(slots, path, true, || Ok::<_, Infallible>(2)).unwrap().unwrap();
+            assert_eq!(second.value, 2);
+
+            finish_first.send(()).unwrap();
+            assert!(first.join().unwrap().unwrap().is_none());
+        });
+
+        let cached = cache.get_or_try_insert(&slots, path, false, || Ok::<_, Infallible>(3)).unwrap().unwrap();
+        assert_eq!(cached.value, 2);
+        assert_eq!(cached.generation, 2);
+    }
+
+    #[test]
+    fn reload_replaces_the_cached_value() {
+        let cache = ResourceCache::new();
+        let slots = Mutex::new(Slots::<u32>::default());
+        let path = Path::new("volume.raw");
+
+        cache.get_or_try_insert(&slots, path, false, || Ok::<_, Infallible>(1)).unwrap();
+        cache.get_or_try_insert(&slots, path, true, || Ok::<_, Infallible>(2)).unwrap();
+
+        let cached = cache.get_or_try_insert(&slots, path, false, || Ok::<_, Infallible>(3)).unwrap().unwrap();
+        assert_eq!(cached.value, 2);
+    }
+}
@@ -0,0 +1,127 @@
+use cgmath::{Deg, InnerSpace, Matrix4, Point3, Rad, Vector3};
+
+const MIN_FOV: f32 = 20.0;
+const MAX_FOV: f32 = 100.0;
+const MIN_RADIUS: f32 = 0.5;
+const MAX_RADIUS: f32 = 50.0;
+
+// Degrees of field of view per scroll line at zoom speed 1.
+const FOV_STEP: f32 = 2.5;
+// Fraction of the orbit radius per scroll line at zoom speed 1.
+const RADIUS_STEP: f32 = 0.1;
+
+// Clip plane limits. The near plane has to stay above zero for a perspective
+// projection, and at least this far in front of the far plane.
+const MIN_NEAR: f32 = 0.001;
+const MAX_FAR: f32 = 100_000.0;
+const MIN_FAR_RATIO: f32 = 2.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraMode {
+    // Eye stays put, zooming narrows or widens the field of view.
+    Perspective,
+    // Eye circles the target, zooming moves it closer or further away.
+    Orbit,
+}
+
+pub struct Camera {
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub up: Vector3<f32>,
+    pub fov_y: Deg<f32>,
+    pub mode: CameraMode,
+    pub zoom_speed: f32,
+    // map near to 1 and far to 0, for a reversed-Z depth buffer
+    pub reversed_z: bool,
+    // see `set_clip_planes`
+    near: f32,
+    far: f32,
+}
+
+impl Camera {
+    pub fn new(eye: Point3<f32>, target: Point3<f32>) -> Self {
+        Camera {
+            eye,
+            target,
+            up: Vector3::unit_y(),
+            fov_y: Deg(60.0),
+            mode: CameraMode::Orbit,
+            zoom_speed: 1.0,
+            reversed_z: false,
+            near: 0.1,
+            far: 100.0,
+        }
+    }
+
+    pub fn view(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    pub fn projection(&self, aspect: f32) -> Matrix4<f32> {
+        // swapping the planes is all it takes to reverse the depth mapping
+        if self.reversed_z {
+            perspective(self.fov_y.into(), aspect, self.far, self.near)
+        } else {
+            perspective(self.fov_y.into(), aspect, self.near, self.far)
+        }
+    }
+
+    pub fn clip_planes(&self) -> (f32, f32) {
+        (self.near, self.far)
+    }
+
+    // Clamped so that 0 < near < far, however far out of range the request is.
+    pub fn set_clip_planes(&mut self, near: f32, far: f32) {
+        self.far = far.clamp(MIN_NEAR * MIN_FAR_RATIO, MAX_FAR);
+        self.near = near.clamp(MIN_NEAR, self.far / MIN_FAR_RATIO);
+    }
+
+    pub fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            CameraMode::Perspective => CameraMode::Orbit,
+            CameraMode::Orbit => CameraMode::Perspective,
+        };
+    }
+
+    // `lines` is the accumulated scroll amount, positive when scrolling away from the user.
+    pub fn zoom(&mut self, lines: f32) {
+        match self.mode {
+            CameraMode::Perspective => {
+                let fov = self.fov_y.0 - lines * FOV_STEP * self.zoom_speed;
+                self.fov_y = Deg(fov.clamp(MIN_FOV, MAX_FOV));
+            }
+            CameraMode::Orbit => {
+                let offset = self.eye - self.target;
+                let factor = (1.0 - RADIUS_STEP * self.zoom_speed).max(0.01).powf(lines);
+                let radius = (offset.magnitude() * factor).clamp(MIN_RADIUS, MAX_RADIUS);
+                self.eye = self.target + offset.normalize_to(radius);
+            }
+        }
+    }
+
+    // Rotates the eye around the target by the given yaw and pitch, in radians.
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+        let offset = self.eye - self.target;
+        let radius = offset.magnitude();
+
+        let yaw = offset.z.atan2(offset.x) + yaw;
+        let pitch = ((offset.y / radius).asin() + pitch).clamp(-1.5, 1.5);
+
+        self.eye = self.target
+            + Vector3::new(pitch.cos() * yaw.cos(), pitch.sin(), pitch.cos() * yaw.sin()) * radius;
+    }
+}
+
+// Right-handed perspective projection for Vulkan's clip space: depth goes to [0, 1]
+// and Y is flipped, since Vulkan's framebuffer Y axis points down.
+pub fn perspective(fov_y: Rad<f32>, aspect: f32, near: f32, far: f32) -> Matrix4<f32> {
+    let f = 1.0 / (fov_y.0 / 2.0).tan();
+
+    // columns, not rows
+    Matrix4::new(
+        f / aspect, 0.0, 0.0,                          0.0,
+        0.0,        -f,  0.0,                          0.0,
+        0.0,        0.0, far / (near - far),           -1.0,
+        0.0,        0.0, near * far / (near - far),    0.0,
+    )
+}
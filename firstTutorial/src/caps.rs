@@ -0,0 +1,40 @@
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::swapchain::Surface;
+
+// Prints what the surface supports on `physical_device`, for `--caps`. These are what
+// the swapchain is created from, so a machine the tutorial misbehaves on usually
+// shows why here.
+pub fn print_surface_caps(physical_device: &PhysicalDevice, surface: &Surface) {
+    let properties = physical_device.properties();
+    println!("Surface capabilities on {} ({:?}):", properties.device_name, properties.device_type);
+
+    let caps = physical_device.surface_capabilities(surface, Default::default()).unwrap();
+    let max_image_count = caps.max_image_count.map_or("unlimited".to_owned(), |count| count.to_string());
+    println!("  image count:       {} to {}", caps.min_image_count, max_image_count);
+    match caps.current_extent {
+        Some([width, height]) => println!("  current extent:    {}x{}", width, height),
+        None => println!("  current extent:    set by the swapchain"),
+    }
+    println!("  min extent:        {}x{}", caps.min_image_extent[0], caps.min_image_extent[1]);
+    println!("  max extent:        {}x{}", caps.max_image_extent[0], caps.max_image_extent[1]);
+    println!("  max array layers:  {}", caps.max_image_array_layers);
+    println!("  current transform: {:?}", caps.current_transform);
+    println!("  transforms:        {:?}", caps.supported_transforms.iter().collect::<Vec<_>>());
+    println!("  composite alpha:   {:?}", caps.supported_composite_alpha.iter().collect::<Vec<_>>());
+    println!("  usage:             {:?}", caps.supported_usage_flags);
+    println!("  exclusive full screen: {}", caps.full_screen_exclusive_supported);
+
+    // only ones the instance has the extensions for are reported, HDR colour spaces
+    // need VK_EXT_swapchain_colorspace
+    let formats = physical_device.surface_formats(surface, Default::default()).unwrap();
+    println!("Surface formats ({}):", formats.len());
+    for (format, color_space) in formats {
+        println!("  {:?} / {:?}", format, color_space);
+    }
+
+    let present_modes = physical_device.surface_present_modes(surface).unwrap().collect::<Vec<_>>();
+    println!("Present modes ({}):", present_modes.len());
+    for present_mode in present_modes {
+        println!("  {:?}", present_mode);
+    }
+}
@@ -0,0 +1,355 @@
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::allocator::CommandBufferAllocator;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CopyImageToBufferInfo};
+use vulkano::format::Format;
+use vulkano::image::{ImageAccess, SwapchainImage};
+use vulkano::memory::allocator::StandardMemoryAllocator;
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// A copy of an image in host memory. `copy` records it into a command buffer, `take`
+// reads it back once that has finished executing. The buffer is kept for the next
+// copy of the same size.
+#[derive(Default)]
+struct Readback {
+    buffer: Option<Arc<CpuAccessibleBuffer<[u8]>>>,
+    // extent and format of the copy recorded for the frame in flight
+    pending: Option<([u32; 2], Format)>,
+}
+
+impl Readback {
+    fn copy<L, A>(
+        &mut self,
+        memory_allocator: &StandardMemoryAllocator,
+        image: Arc<dyn ImageAccess>,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+    ) -> Result<(), String>
+    where
+        A: CommandBufferAllocator,
+    {
+        let extent = image.dimensions().width_height();
+        let format = image.format();
+
+        if !image.inner().image.usage().transfer_src {
+            return Err(format!("the {:?} image was created without transfer_src", format));
+        }
+
+        let len = extent[0] as u64 * extent[1] as u64 * format.block_size().unwrap();
+        let buffer = match &self.buffer {
+            Some(buffer) if buffer.len() == len => buffer.clone(),
+            _ => {
+                let buffer = CpuAccessibleBuffer::from_iter(
+                    memory_allocator,
+                    BufferUsage {
+                        transfer_dst: true,
+                        ..BufferUsage::empty()
+                    },
+                    true,
+                    (0..len).map(|_| 0u8),
+                )
+                    .unwrap();
+                self.buffer = Some(buffer.clone());
+                buffer
+            }
+        };
+
+        builder
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, buffer))
+            .unwrap();
+        self.pending = Some((extent, format));
+
+        Ok(())
+    }
+
+    // The extent and RGBA pixels of the last copy, see `to_rgba8`.
+    fn take(&mut self) -> Option<([u32; 2], Vec<u8>)> {
+        let (extent, format) = self.pending.take()?;
+        let data = self.buffer.as_ref().unwrap().read().unwrap();
+
+        Some((extent, to_rgba8(format, &data)))
+    }
+}
+
+// Swapchain images are saved as they are, so only 8-bit ones are.
+fn check_swapchain_image(image: &SwapchainImage) -> Result<(), String> {
+    let format = image.format();
+
+    if !matches!(format, Format::B8G8R8A8_SRGB | Format::B8G8R8A8_UNORM | Format::R8G8B8A8_SRGB | Format::R8G8B8A8_UNORM) {
+        return Err(format!("it needs an 8-bit RGBA or BGRA swapchain, got {:?} (is --hdr on?)", format));
+    }
+    if !image.inner().image.usage().transfer_src {
+        return Err("the swapchain images can't be copied from on this surface".to_owned());
+    }
+
+    Ok(())
+}
+
+fn converts_to_rgba8(format: Format) -> bool {
+    matches!(
+        format,
+        Format::R8G8B8A8_SRGB
+            | Format::R8G8B8A8_UNORM
+            | Format::B8G8R8A8_SRGB
+            | Format::B8G8R8A8_UNORM
+            | Format::R16G16B16A16_SFLOAT
+            | Format::R16G16_SFLOAT
+    )
+}
+
+// 8-bit RGBA for a PNG. 8-bit colour is copied, BGRA swizzled around. HDR colour is
+// clamped and sRGB encoded, and two-channel velocity is shown like its debug view.
+fn to_rgba8(format: Format, data: &[u8]) -> Vec<u8> {
+    let halves = || data.chunks_exact(2).map(|half| f16_to_f32(u16::from_le_bytes([half[0], half[1]])));
+
+    match format {
+        Format::R8G8B8A8_SRGB | Format::R8G8B8A8_UNORM => data.to_vec(),
+        Format::B8G8R8A8_SRGB | Format::B8G8R8A8_UNORM => {
+            let mut pixels = data.to_vec();
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+            pixels
+        }
+        Format::R16G16B16A16_SFLOAT => halves()
+            .enumerate()
+            .map(|(i, value)| if i % 4 == 3 { unorm8(value) } else { unorm8(srgb_encode(value)) })
+            .collect(),
+        Format::R16G16_SFLOAT => halves()
+            .collect::<Vec<_>>()
+            .chunks_exact(2)
+            .flat_map(|velocity| [unorm8(velocity[0] * 5.0 + 0.5), unorm8(velocity[1] * 5.0 + 0.5), 128, 255])
+            .collect(),
+        _ => panic!("No PNG conversion for {:?}", format),
+    }
+}
+
+fn unorm8(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn srgb_encode(linear: f32) -> f32 {
+    let linear = linear.clamp(0.0, 1.0);
+    if linear <= 0.0031308 { linear * 12.92 } else { 1.055 * linear.powf(1.0 / 2.4) - 0.055 }
+}
+
+// IEEE 754 half to float, denormals included.
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+fn write_png(path: &Path, extent: [u32; 2], pixels: &[u8]) -> Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), extent[0], extent[1]);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(pixels)?;
+
+    Ok(())
+}
+
+// Saves rendered frames as `dir/frame_00000.png`, `dir/frame_00001.png`, ... for
+// turning into a video afterwards, e.g. `ffmpeg -i frame_%05d.png out.mp4`.
+//
+// Every frame goes through `copy_frame` while its command buffer is being built,
+// then `save` once that frame's fence has signalled.
+pub struct FrameRecorder {
+    dir: PathBuf,
+    frame_limit: Option<u32>,
+    frames_written: u32,
+    readback: Readback,
+}
+
+impl FrameRecorder {
+    pub fn new(dir: PathBuf, frame_limit: Option<u32>) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+
+        Ok(FrameRecorder {
+            dir,
+            frame_limit,
+            frames_written: 0,
+            readback: Readback::default(),
+        })
+    }
+
+    // Records a copy of the finished swapchain image into the readback buffer, or says
+    // why this swapchain can't be captured.
+    pub fn copy_frame<L, A>(
+        &mut self,
+        memory_allocator: &StandardMemoryAllocator,
+        image: Arc<SwapchainImage>,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+    ) -> Result<(), String>
+    where
+        A: CommandBufferAllocator,
+    {
+        check_swapchain_image(&image)?;
+        self.readback.copy(memory_allocator, image, builder)
+    }
+
+    // Writes out the frame copied by the last `copy_frame`. Only call this once its
+    // command buffer has finished executing.
+    pub fn save(&mut self) -> Result<(), png::EncodingError> {
+        let (extent, pixels) = match self.readback.take() {
+            Some(frame) => frame,
+            None => return Ok(()),
+        };
+
+        write_png(&self.dir.join(format!("frame_{:05}.png", self.frames_written)), extent, &pixels)?;
+
+        self.frames_written += 1;
+        match self.frame_limit {
+            Some(limit) => println!("Recorded frame {}/{}", self.frames_written, limit),
+            None => println!("Recorded frame {}", self.frames_written),
+        }
+
+        Ok(())
+    }
+
+    pub fn is_done(&self) -> bool {
+        matches!(self.frame_limit, Some(limit) if self.frames_written >= limit)
+    }
+
+    pub fn frames_written(&self) -> u32 {
+        self.frames_written
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+// Puts single frames on the system clipboard, or where that fails saves them as
+// `screenshot_000.png`, `screenshot_001.png`, ... in the working directory instead.
+// Used like `FrameRecorder`: `copy_frame`, then `finish` after the fence.
+#[derive(Default)]
+pub struct ClipboardCapture {
+    readback: Readback,
+    // kept open, on X11 the clipboard contents are served from here
+    clipboard: Option<arboard::Clipboard>,
+}
+
+impl ClipboardCapture {
+    // Like `FrameRecorder::copy_frame`.
+    pub fn copy_frame<L, A>(
+        &mut self,
+        memory_allocator: &StandardMemoryAllocator,
+        image: Arc<SwapchainImage>,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+    ) -> Result<(), String>
+    where
+        A: CommandBufferAllocator,
+    {
+        check_swapchain_image(&image)?;
+        self.readback.copy(memory_allocator, image, builder)
+    }
+
+    // Hands over the frame copied by the last `copy_frame`. Only call this once its
+    // command buffer has finished executing.
+    pub fn finish(&mut self) {
+        let (extent, pixels) = match self.readback.take() {
+            Some(frame) => frame,
+            None => return,
+        };
+
+        let error = match self.set_clipboard(extent, &pixels) {
+            Ok(()) => {
+                println!("Copied the {}x{} frame to the clipboard", extent[0], extent[1]);
+                return;
+            }
+            Err(e) => e,
+        };
+
+        let path = (0..)
+            .map(|i| PathBuf::from(format!("screenshot_{:03}.png", i)))
+            .find(|path| !path.exists())
+            .unwrap();
+        match write_png(&path, extent, &pixels) {
+            Ok(()) => println!("Clipboard unavailable ({}), saved {} instead", error, path.display()),
+            Err(e) => eprintln!("Clipboard unavailable ({}), and saving {} failed: {}", error, path.display(), e),
+        }
+    }
+
+    fn set_clipboard(&mut self, extent: [u32; 2], pixels: &[u8]) -> Result<(), arboard::Error> {
+        if self.clipboard.is_none() {
+            self.clipboard = Some(arboard::Clipboard::new()?);
+        }
+
+        self.clipboard.as_mut().unwrap().set_image(arboard::ImageData {
+            width: extent[0] as usize,
+            height: extent[1] as usize,
+            bytes: pixels.into(),
+        })
+    }
+}
+
+// Saves the render targets of a single frame as PNGs into a new
+// `frame_dump_<unix time in ms>` directory, with `_1`, `_2`, ... added should that
+// already exist, for looking at what each pass produced. Each
+// target gets its own `copy`, then `save` once the frame's fence has signalled.
+pub struct FrameDump {
+    dir: PathBuf,
+    targets: Vec<(String, Readback)>,
+}
+
+impl FrameDump {
+    pub fn new() -> io::Result<Self> {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+        let dir = (0..)
+            .map(|i| match i {
+                0 => PathBuf::from(format!("frame_dump_{}", time)),
+                i => PathBuf::from(format!("frame_dump_{}_{}", time, i)),
+            })
+            .find(|dir| !dir.exists())
+            .unwrap();
+        fs::create_dir(&dir)?;
+
+        Ok(FrameDump {
+            dir,
+            targets: Vec::new(),
+        })
+    }
+
+    pub fn copy<L, A>(
+        &mut self,
+        name: &str,
+        memory_allocator: &StandardMemoryAllocator,
+        image: Arc<dyn ImageAccess>,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+    ) where
+        A: CommandBufferAllocator,
+    {
+        if !converts_to_rgba8(image.format()) {
+            println!("Not dumping {}, there's no PNG conversion for {:?}", name, image.format());
+            return;
+        }
+
+        let mut readback = Readback::default();
+        match readback.copy(memory_allocator, image, builder) {
+            Ok(()) => self.targets.push((name.to_owned(), readback)),
+            Err(e) => println!("Not dumping {}, {}", name, e),
+        }
+    }
+
+    // Only call this once the command buffer with the copies has finished executing.
+    pub fn save(mut self) -> Result<PathBuf, png::EncodingError> {
+        for (name, readback) in &mut self.targets {
+            if let Some((extent, pixels)) = readback.take() {
+                write_png(&self.dir.join(format!("{}.png", name)), extent, &pixels)?;
+            }
+        }
+
+        Ok(self.dir)
+    }
+}
@@ -0,0 +1,61 @@
+use std::time::Instant;
+
+// Timestep used when single-stepping a paused clock.
+pub const FIXED_TIMESTEP: f32 = 1.0 / 60.0;
+
+// Drives scene animation. Unlike wall-clock time it can be frozen and nudged
+// forward one fixed step at a time, while the rest of the frame keeps running.
+pub struct AnimationClock {
+    time: f32,
+    paused: bool,
+    last_tick: Instant,
+}
+
+impl AnimationClock {
+    pub fn new() -> Self {
+        AnimationClock {
+            time: 0.0,
+            paused: false,
+            last_tick: Instant::now(),
+        }
+    }
+
+    // Advances by the real time since the previous tick unless paused, and
+    // returns the animation time in seconds.
+    pub fn tick(&mut self) -> f32 {
+        let now = Instant::now();
+
+        if !self.paused {
+            self.time += now.duration_since(self.last_tick).as_secs_f32();
+        }
+
+        self.last_tick = now;
+        self.time
+    }
+
+    // Advances by exactly `dt` unless paused, however long the frame really took.
+    // Used while recording, so the output plays back at an even speed.
+    pub fn advance(&mut self, dt: f32) -> f32 {
+        if !self.paused {
+            self.time += dt;
+        }
+
+        self.last_tick = Instant::now();
+        self.time
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // Only meaningful while paused; a running clock moves on by itself.
+    pub fn step(&mut self) {
+        if self.paused {
+            self.time += FIXED_TIMESTEP;
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::fxaa::FxaaQuality;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AntiAliasing {
+    None,
+    Msaa,
+    Fxaa,
+}
+
+impl FromStr for AntiAliasing {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "none" => Ok(AntiAliasing::None),
+            "msaa" => Ok(AntiAliasing::Msaa),
+            "fxaa" => Ok(AntiAliasing::Fxaa),
+            _ => Err(()),
+        }
+    }
+}
+
+// Settings that can be overridden from the command line, e.g.
+// `cargo run -- --zoom-speed 2.0`.
+pub struct Config {
+    pub zoom_speed: f32,
+    // How long to wait on the GPU before treating it as hung.
+    pub fence_timeout: Duration,
+    // Most frames per second to render, 0 for no limit.
+    pub fps_cap: u32,
+    // Queue priorities from 0 to 1, for rendering and for the transfer queue
+    // streamed uploads go through (when the device has a separate one).
+    pub graphics_priority: f32,
+    pub transfer_priority: f32,
+    // Raw 8-bit volume to ray-march next to the scene, and its size in voxels.
+    pub volume: Option<PathBuf>,
+    pub volume_size: [u32; 3],
+    // Ask for an HDR swapchain, and the brightness in nits of a 1.0 shader output on it.
+    pub hdr: bool,
+    pub paper_white: f32,
+    // Scale applied to the HDR scene colour before tonemapping.
+    pub exposure: f32,
+    // Which antialiasing to use, and the FXAA preset when that's the one.
+    pub aa: AntiAliasing,
+    pub fxaa_quality: FxaaQuality,
+    // Blur along the motion vectors: the fraction of a frame's motion the shutter
+    // is open for, and the most samples taken along it.
+    pub motion_blur: bool,
+    pub motion_blur_strength: f32,
+    pub motion_blur_samples: u32,
+    // MSAA samples per pixel (1 disables it), and the fraction of those the mesh
+    // fragment shader runs for (0 disables per-sample shading).
+    pub msaa: u32,
+    pub sample_shading: f32,
+    // Fetch mesh vertices through buffer device addresses where supported.
+    pub vertex_pulling: bool,
+    // Use VK_KHR_dynamic_rendering instead of a render pass where supported.
+    pub dynamic_rendering: bool,
+    // Start with the depth prepass on (Z toggles it).
+    pub depth_prepass: bool,
+    // Start with the wireframe overlay on (W toggles it).
+    pub wireframe: bool,
+    // Width in pixels of the wireframe overlay's lines (, and / change it), clamped
+    // to what the device supports.
+    pub line_width: f32,
+    // Reversed-Z with a float depth buffer instead of conventional D16 depth.
+    pub reversed_z: bool,
+    // Directory to save every frame to as a PNG, the animation rate while doing so,
+    // and how many frames to record before exiting (`None` for until closed).
+    pub record: Option<PathBuf>,
+    pub record_fps: u32,
+    pub record_frames: Option<u32>,
+    // Print what the window's surface supports and exit.
+    pub caps: bool,
+    // Time every pass on the GPU and print the averages every 120 frames.
+    pub gpu_profile: bool,
+    // RON file describing the passes of a frame, `render_graph.ron` when unset.
+    pub render_graph: Option<PathBuf>,
+    // Set by `make_safe`, also turns on validation where the layer is installed.
+    pub safe_mode: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            zoom_speed: 1.0,
+            fence_timeout: Duration::from_secs(5),
+            fps_cap: 0,
+            graphics_priority: 1.0,
+            transfer_priority: 0.5,
+            volume: None,
+            volume_size: [64, 64, 64],
+            hdr: false,
+            paper_white: 200.0,
+            exposure: 1.0,
+            aa: AntiAliasing::Msaa,
+            fxaa_quality: FxaaQuality::default(),
+            motion_blur: false,
+            motion_blur_strength: 0.5,
+            motion_blur_samples: 16,
+            msaa: 4,
+            sample_shading: 1.0,
+            vertex_pulling: false,
+            dynamic_rendering: false,
+            depth_prepass: false,
+            wireframe: false,
+            line_width: 1.0,
+            reversed_z: false,
+            record: None,
+            record_fps: 30,
+            record_frames: None,
+            caps: false,
+            gpu_profile: false,
+            render_graph: None,
+            safe_mode: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_args() -> Self {
+        let mut config = Config::default();
+        let mut args = std::env::args().skip(1);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--zoom-speed" => config.zoom_speed = parse_value(&arg, args.next()),
+                "--fence-timeout" => {
+                    config.fence_timeout = Duration::from_secs_f32(parse_value(&arg, args.next()));
+                }
+                "--fps-cap" => config.fps_cap = parse_value(&arg, args.next()),
+                "--graphics-priority" => {
+                    config.graphics_priority = parse_value::<f32>(&arg, args.next()).clamp(0.0, 1.0);
+                }
+                "--transfer-priority" => {
+                    config.transfer_priority = parse_value::<f32>(&arg, args.next()).clamp(0.0, 1.0);
+                }
+                "--volume" => config.volume = Some(parse_value(&arg, args.next())),
+                "--volume-size" => config.volume_size = parse_dimensions(&arg, args.next()),
+                "--hdr" => config.hdr = true,
+                "--paper-white" => config.paper_white = parse_value(&arg, args.next()),
+                "--exposure" => config.exposure = parse_value(&arg, args.next()),
+                "--aa" => config.aa = parse_value(&arg, args.next()),
+                "--fxaa-quality" => config.fxaa_quality = parse_value(&arg, args.next()),
+                "--motion-blur" => config.motion_blur = true,
+                "--motion-blur-strength" => config.motion_blur_strength = parse_value(&arg, args.next()),
+                "--motion-blur-samples" => config.motion_blur_samples = parse_value::<u32>(&arg, args.next()).max(1),
+                "--msaa" => config.msaa = parse_value(&arg, args.next()),
+                "--sample-shading" => {
+                    config.sample_shading = parse_value::<f32>(&arg, args.next()).clamp(0.0, 1.0);
+                }
+                "--vertex-pulling" => config.vertex_pulling = true,
+                "--dynamic-rendering" => config.dynamic_rendering = true,
+                "--depth-prepass" => config.depth_prepass = true,
+                "--wireframe" => config.wireframe = true,
+                "--line-width" => config.line_width = parse_value(&arg, args.next()),
+                "--reversed-z" => config.reversed_z = true,
+                "--record" => config.record = Some(parse_value(&arg, args.next())),
+                "--record-fps" => config.record_fps = parse_value::<u32>(&arg, args.next()).max(1),
+                "--record-frames" => config.record_frames = Some(parse_value(&arg, args.next())),
+                "--caps" => config.caps = true,
+                "--gpu-profile" => config.gpu_profile = true,
+                "--render-graph" => config.render_graph = Some(parse_value(&arg, args.next())),
+                other => eprintln!("Ignoring unknown argument `{}`", other),
+            }
+        }
+
+        config
+    }
+
+    // Conservative settings to start with after a crash: every optional feature off,
+    // whatever the command line asked for. Presenting is always FIFO already.
+    pub fn make_safe(&mut self) {
+        self.safe_mode = true;
+        self.volume = None;
+        self.hdr = false;
+        self.aa = AntiAliasing::None;
+        self.motion_blur = false;
+        self.msaa = 1;
+        self.sample_shading = 0.0;
+        self.vertex_pulling = false;
+        self.dynamic_rendering = false;
+        self.depth_prepass = false;
+        self.wireframe = false;
+        self.line_width = 1.0;
+        self.reversed_z = false;
+        self.gpu_profile = false;
+        self.render_graph = None;
+        // `fps_cap` stays, a frame limit only ever takes load off the GPU
+    }
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> T {
+    value
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_else(|| panic!("`{}` expects a valid value", flag))
+}
+
+// Parses `WxHxD`, e.g. `256x256x128`.
+fn parse_dimensions(flag: &str, value: Option<String>) -> [u32; 3] {
+    let dimensions = value
+        .map(|v| v.split('x').map(|d| d.parse().ok()).collect::<Option<Vec<u32>>>())
+        .unwrap_or_default();
+
+    match dimensions.as_deref() {
+        Some(&[w, h, d]) => [w, h, d],
+        _ => panic!("`{}` expects dimensions like 64x64x64", flag),
+    }
+}
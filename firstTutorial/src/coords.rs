@@ -0,0 +1,146 @@
+// Conversions between world space, normalized device coordinates and the screen.
+//
+// Vulkan NDC has X and Y in [-1, 1] and depth in [0, 1], reversed or not (see
+// `DepthMode`). `camera::perspective` flips Y, so NDC Y points down like window
+// coordinates do and no conversion here flips it again. Screen positions are pixels
+// from the window's top left, physical like `CursorMoved` positions and `Viewport`.
+//
+// For picking, gizmos and UI, none of which exist yet: `world_to_screen`,
+// `screen_to_world_ray` and `Ray` are allowed to go unused until they do.
+
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Vector3, Vector4};
+use vulkano::pipeline::graphics::viewport::Viewport;
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    // unit length
+    pub direction: Vector3<f32>,
+}
+
+// Screen position and depth of `point`, or `None` when it's behind the camera.
+#[allow(dead_code)]
+pub fn world_to_screen(point: Point3<f32>, view: Matrix4<f32>, proj: Matrix4<f32>, viewport: &Viewport) -> Option<[f32; 3]> {
+    let clip = proj * view * point.to_homogeneous();
+    if clip.w <= 0.0 {
+        return None;
+    }
+
+    let [x, y] = ndc_to_screen([clip.x / clip.w, clip.y / clip.w], viewport);
+    Some([x, y, clip.z / clip.w])
+}
+
+pub fn ndc_to_screen(ndc: [f32; 2], viewport: &Viewport) -> [f32; 2] {
+    [
+        viewport.origin[0] + (ndc[0] + 1.0) * 0.5 * viewport.dimensions[0],
+        viewport.origin[1] + (ndc[1] + 1.0) * 0.5 * viewport.dimensions[1],
+    ]
+}
+
+pub fn screen_to_ndc(screen: [f32; 2], viewport: &Viewport) -> [f32; 2] {
+    [
+        (screen[0] - viewport.origin[0]) / viewport.dimensions[0] * 2.0 - 1.0,
+        (screen[1] - viewport.origin[1]) / viewport.dimensions[1] * 2.0 - 1.0,
+    ]
+}
+
+// The ray from the eye through `ndc`. Every depth a perspective projection maps there
+// lies on it, so it takes any point in between the planes and doesn't care which way
+// round depth goes.
+pub fn ndc_to_world_ray(ndc: [f32; 2], view: Matrix4<f32>, proj: Matrix4<f32>) -> Ray {
+    let origin = Point3::from_homogeneous(view.invert().unwrap().w);
+    let through = Point3::from_homogeneous((proj * view).invert().unwrap() * Vector4::new(ndc[0], ndc[1], 0.5, 1.0));
+
+    Ray {
+        origin,
+        direction: (through - origin).normalize(),
+    }
+}
+
+#[allow(dead_code)]
+pub fn screen_to_world_ray(screen: [f32; 2], view: Matrix4<f32>, proj: Matrix4<f32>, viewport: &Viewport) -> Ray {
+    ndc_to_world_ray(screen_to_ndc(screen, viewport), view, proj)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::{Deg, EuclideanSpace};
+
+    use crate::camera::perspective;
+
+    const EPSILON: f32 = 1e-3;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < EPSILON, "{} is not close to {}", a, b);
+    }
+
+    fn viewport(origin: [f32; 2], dimensions: [f32; 2]) -> Viewport {
+        Viewport {
+            origin,
+            dimensions,
+            depth_range: 0.0..1.0,
+        }
+    }
+
+    fn view() -> Matrix4<f32> {
+        Matrix4::look_at_rh(Point3::new(2.0, 1.5, 4.0), Point3::origin(), Vector3::unit_y())
+    }
+
+    // the same planes `Camera::projection` uses, swapped for reversed-Z
+    fn projections() -> [Matrix4<f32>; 2] {
+        [
+            perspective(Deg(60.0).into(), 1.5, 0.1, 100.0),
+            perspective(Deg(60.0).into(), 1.5, 100.0, 0.1),
+        ]
+    }
+
+    #[test]
+    fn world_to_screen_round_trips_through_the_ray() {
+        let point = Point3::new(0.3, -0.2, 0.5);
+        let viewport = viewport([0.0, 0.0], [1200.0, 800.0]);
+
+        for proj in projections() {
+            let [x, y, depth] = world_to_screen(point, view(), proj, &viewport).unwrap();
+            assert!((0.0..=1.0).contains(&depth), "depth {} is outside [0, 1]", depth);
+
+            let ray = screen_to_world_ray([x, y], view(), proj, &viewport);
+            let to_point = point - ray.origin;
+            let along = to_point.dot(ray.direction);
+            assert!(along > 0.0, "the point is behind the ray");
+            assert_close((to_point - ray.direction * along).magnitude(), 0.0);
+        }
+    }
+
+    #[test]
+    fn screen_and_ndc_round_trip_in_an_offset_viewport() {
+        // the right half of a split screen
+        let viewport = viewport([600.0, 0.0], [600.0, 800.0]);
+
+        let top_left = ndc_to_screen([-1.0, -1.0], &viewport);
+        let bottom_right = ndc_to_screen([1.0, 1.0], &viewport);
+        assert_close(top_left[0], 600.0);
+        assert_close(top_left[1], 0.0);
+        assert_close(bottom_right[0], 1200.0);
+        assert_close(bottom_right[1], 800.0);
+
+        for ndc in [[0.0, 0.0], [-0.25, 0.5], [0.9, -0.75]] {
+            let back = screen_to_ndc(ndc_to_screen(ndc, &viewport), &viewport);
+            assert_close(back[0], ndc[0]);
+            assert_close(back[1], ndc[1]);
+        }
+    }
+
+    #[test]
+    fn world_to_screen_is_none_behind_the_eye() {
+        let viewport = viewport([0.0, 0.0], [1200.0, 800.0]);
+        // further from the origin than the eye, along the same line
+        let behind = Point3::new(4.0, 3.0, 8.0);
+
+        for proj in projections() {
+            assert_eq!(world_to_screen(behind, view(), proj, &viewport), None);
+        }
+    }
+}
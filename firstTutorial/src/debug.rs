@@ -0,0 +1,25 @@
+use vulkano::device::DeviceOwned;
+use vulkano::VulkanObject;
+
+use std::fmt;
+
+// Gives `object` a human-readable name in validation messages and in tools like
+// RenderDoc. Does nothing unless VK_EXT_debug_utils is enabled on the instance.
+pub fn set_name<T: VulkanObject + DeviceOwned>(object: &T, name: &str) {
+    let device = object.device();
+
+    if !device.instance().enabled_extensions().ext_debug_utils {
+        return;
+    }
+
+    if let Err(e) = device.set_debug_utils_object_name(object, Some(name)) {
+        println!("Failed to name object `{}`: {:?}", name, e);
+    }
+}
+
+// Diagnostics that are routine and only worth printing in debug builds.
+pub fn log(args: fmt::Arguments) {
+    if cfg!(debug_assertions) {
+        println!("[debug] {}", args);
+    }
+}
@@ -0,0 +1,41 @@
+use winit::event::VirtualKeyCode;
+
+// What the mesh fragment shader writes out. The discriminants are what the shader
+// switches on through `Globals.debug_view`, keep them in sync with `main.rs`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DebugView {
+    #[default]
+    Shaded = 0,
+    // world-space normals mapped from [-1, 1] to [0, 1]
+    Normals = 1,
+    // linear view depth, black at the camera and white at the far plane
+    Depth = 2,
+    Uvs = 3,
+    // unlit surface colour
+    Albedo = 4,
+    // every fragment adds a fixed amount, depth testing off
+    Overdraw = 5,
+    // motion since the last frame, red for X and green for Y around a still grey
+    Velocity = 6,
+}
+
+impl DebugView {
+    // Number keys 1 to 7, in declaration order.
+    pub fn from_key(key: VirtualKeyCode) -> Option<Self> {
+        match key {
+            VirtualKeyCode::Key1 => Some(DebugView::Shaded),
+            VirtualKeyCode::Key2 => Some(DebugView::Normals),
+            VirtualKeyCode::Key3 => Some(DebugView::Depth),
+            VirtualKeyCode::Key4 => Some(DebugView::Uvs),
+            VirtualKeyCode::Key5 => Some(DebugView::Albedo),
+            VirtualKeyCode::Key6 => Some(DebugView::Overdraw),
+            VirtualKeyCode::Key7 => Some(DebugView::Velocity),
+            _ => None,
+        }
+    }
+
+    // Debug output is data, not a picture; it skips exposure and the tonemap curve.
+    pub fn is_debug(self) -> bool {
+        self != DebugView::Shaded
+    }
+}
@@ -0,0 +1,76 @@
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::format::Format;
+use vulkano::pipeline::graphics::depth_stencil::{CompareOp, DepthState, DepthStencilState};
+use vulkano::pipeline::StateMode;
+
+// How the depth buffer is laid out. Conventional depth maps near to 0 and far to 1.
+// Reversed-Z maps near to 1 and far to 0, which together with a float format puts
+// the float's extra precision near zero where the perspective divide has the least,
+// so distant surfaces stop z-fighting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DepthMode {
+    pub format: Format,
+    pub reversed: bool,
+}
+
+impl DepthMode {
+    // Reversed-Z takes D32_SFLOAT; without it, or when that can't be a depth
+    // attachment, D16_UNORM as before.
+    pub fn new(physical_device: &PhysicalDevice, reversed: bool) -> Self {
+        if !reversed {
+            return DepthMode { format: Format::D16_UNORM, reversed };
+        }
+
+        let float_depth = physical_device
+            .format_properties(Format::D32_SFLOAT)
+            .optimal_tiling_features
+            .depth_stencil_attachment;
+        if !float_depth {
+            println!("D32_SFLOAT can't be a depth attachment here, reversed-Z falls back to D16_UNORM");
+        }
+
+        DepthMode {
+            format: if float_depth { Format::D32_SFLOAT } else { Format::D16_UNORM },
+            reversed,
+        }
+    }
+
+    // The farthest possible depth.
+    pub fn clear_value(self) -> f32 {
+        if self.reversed { 0.0 } else { 1.0 }
+    }
+
+    // The test that keeps the closer fragment.
+    pub fn closer(self) -> CompareOp {
+        if self.reversed { CompareOp::Greater } else { CompareOp::Less }
+    }
+
+    // Depth bias, in bias units, that moves a surface `amount` towards the camera.
+    pub fn toward_camera(self, amount: f32) -> f32 {
+        if self.reversed { amount } else { -amount }
+    }
+
+    // Passes at or in front of what's already there without writing, for drawing
+    // over surfaces at the same depth.
+    pub fn overlay(self) -> DepthStencilState {
+        DepthStencilState {
+            depth: Some(DepthState {
+                enable_dynamic: false,
+                write_enable: StateMode::Fixed(false),
+                compare_op: StateMode::Fixed(if self.reversed { CompareOp::GreaterOrEqual } else { CompareOp::LessOrEqual }),
+            }),
+            ..DepthStencilState::disabled()
+        }
+    }
+
+    pub fn test(self, write: bool) -> DepthStencilState {
+        DepthStencilState {
+            depth: Some(DepthState {
+                enable_dynamic: false,
+                write_enable: StateMode::Fixed(write),
+                compare_op: StateMode::Fixed(self.closer()),
+            }),
+            ..DepthStencilState::disabled()
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long before a deadline sleeping stops and spinning takes over. Sleeps can
+// overshoot by the scheduler's granularity, a millisecond or more on some systems.
+const SPIN_MARGIN: Duration = Duration::from_millis(2);
+
+// Holds the frame rate at or under a cap by waiting out what's left of every frame's
+// time slot, whatever the present mode. Also prints the rate actually reached once a
+// second while capped.
+pub struct FrameLimiter {
+    // `None` when uncapped
+    frame_time: Option<Duration>,
+    fps_cap: u32,
+    deadline: Instant,
+    report_start: Instant,
+    report_frames: u32,
+}
+
+impl FrameLimiter {
+    // A cap of 0 means uncapped.
+    pub fn new(fps_cap: u32) -> Self {
+        let now = Instant::now();
+
+        FrameLimiter {
+            frame_time: (fps_cap > 0).then(|| Duration::from_secs(1) / fps_cap),
+            fps_cap,
+            deadline: now,
+            report_start: now,
+            report_frames: 0,
+        }
+    }
+
+    // Call once at the end of every frame.
+    pub fn wait(&mut self) {
+        let frame_time = match self.frame_time {
+            Some(frame_time) => frame_time,
+            None => return,
+        };
+
+        // deadlines follow on from each other, so one slow frame doesn't shift the rest
+        self.deadline += frame_time;
+        let now = Instant::now();
+        if self.deadline < now {
+            // too far behind to catch up without a burst of frames, start over from here
+            self.deadline = now;
+        } else {
+            if let Some(sleep) = (self.deadline - now).checked_sub(SPIN_MARGIN) {
+                thread::sleep(sleep);
+            }
+            while Instant::now() < self.deadline {
+                std::hint::spin_loop();
+            }
+        }
+
+        self.report_frames += 1;
+        let elapsed = self.report_start.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            println!("FPS: {:.1} (cap {})", self.report_frames as f32 / elapsed.as_secs_f32(), self.fps_cap);
+            self.report_start = Instant::now();
+            self.report_frames = 0;
+        }
+    }
+}
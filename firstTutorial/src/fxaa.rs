@@ -0,0 +1,92 @@
+// FXAA runs after tonemapping on the LDR image, in its own render pass: it blends
+// along edges it finds from neighbouring pixels, which no subpass input can read
+// (see `tonemap`). Much cheaper than MSAA, at the cost of some blur and no help
+// with sub-pixel geometry.
+
+use vulkano::device::Device;
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::render_pass::PipelineRenderPassType;
+use vulkano::pipeline::graphics::vertex_input::VertexInputState;
+use vulkano::pipeline::graphics::viewport::ViewportState;
+use vulkano::pipeline::GraphicsPipeline;
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::sampler::SamplerKey;
+use crate::tonemap;
+
+// FXAA reads between texels, bilinear filtering does the blending.
+pub const SAMPLER: SamplerKey = SamplerKey::linear_clamp();
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FxaaQuality {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+impl FxaaQuality {
+    // `FxaaPush` without the inverse size: subpixel, edge_threshold,
+    // edge_threshold_min and search_steps.
+    fn settings(self) -> (f32, f32, f32, i32) {
+        match self {
+            FxaaQuality::Low => (0.5, 0.25, 0.0833, 4),
+            FxaaQuality::Medium => (0.75, 0.166, 0.0833, 8),
+            FxaaQuality::High => (0.75, 0.125, 0.0625, 12),
+        }
+    }
+
+    pub fn push_constants(self, extent: [u32; 2]) -> fs::ty::FxaaPush {
+        let (subpixel, edge_threshold, edge_threshold_min, search_steps) = self.settings();
+
+        fs::ty::FxaaPush {
+            inverse_size: [1.0 / extent[0] as f32, 1.0 / extent[1] as f32],
+            subpixel,
+            edge_threshold,
+            edge_threshold_min,
+            search_steps,
+        }
+    }
+}
+
+impl FromStr for FxaaQuality {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "low" => Ok(FxaaQuality::Low),
+            "medium" => Ok(FxaaQuality::Medium),
+            "high" => Ok(FxaaQuality::High),
+            _ => Err(()),
+        }
+    }
+}
+
+pub fn create_pipeline(device: Arc<Device>, render_pass: PipelineRenderPassType) -> Arc<GraphicsPipeline> {
+    let vs = tonemap::vs::load(device.clone()).unwrap();
+    let fs = fs::load(device.clone()).unwrap();
+
+    GraphicsPipeline::start()
+        .vertex_input_state(VertexInputState::new())
+        .vertex_shader(vs.entry_point("main").unwrap(), ())
+        .input_assembly_state(InputAssemblyState::new())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_dynamic(1))
+        .fragment_shader(fs.entry_point("main").unwrap(), ())
+        .render_pass(render_pass)
+        .build(device)
+        .unwrap()
+}
+
+pub mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/shaders/fxaa.frag",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
+    }
+}
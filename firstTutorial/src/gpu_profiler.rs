@@ -0,0 +1,194 @@
+// GPU time per pass. Every pass recorded between `begin` and `end` gets a timestamp
+// written before and after it, into a query pool per swapchain image. A slot's
+// results are read when it comes round again, once its fence has been waited on, and
+// summed over REPORT_FRAMES frames before printing the average per pass, slowest
+// first.
+//
+// Timestamps are written at the bottom of the pipe, once everything recorded before
+// them has finished, so a pass's time includes waiting on the work just ahead of it.
+// They can be written inside a render pass, which times the scene and tonemap
+// subpasses apart.
+
+use vulkano::command_buffer::allocator::CommandBufferAllocator;
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::device::Queue;
+use vulkano::query::{QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType};
+use vulkano::sync::PipelineStage;
+
+use std::sync::Arc;
+
+const MAX_PASSES: u32 = 16;
+const REPORT_FRAMES: u32 = 120;
+
+struct FrameQueries {
+    pool: Arc<QueryPool>,
+    // pass `i` has its timestamps at queries `2 * i` and `2 * i + 1`
+    passes: Vec<&'static str>,
+}
+
+pub struct GpuProfiler {
+    // unset when profiling is off, every call does nothing then
+    enabled: bool,
+    queue: Arc<Queue>,
+    // nanoseconds per tick, and the bits of a timestamp that count
+    timestamp_period: f32,
+    timestamp_mask: u64,
+    frames: Vec<FrameQueries>,
+    // the slot being recorded and whether a pass in it is open
+    current: usize,
+    open: bool,
+    // per pass in the order first seen, and the frame from first to last timestamp
+    totals: Vec<(&'static str, f64)>,
+    frame_total: f64,
+    frames_measured: u32,
+}
+
+impl GpuProfiler {
+    // Profiles the passes recorded for `queue` when `enabled` and its family can
+    // write timestamps.
+    pub fn new(queue: Arc<Queue>, enabled: bool) -> Self {
+        let physical_device = queue.device().physical_device();
+        let valid_bits = physical_device.queue_family_properties()[queue.queue_family_index() as usize]
+            .timestamp_valid_bits;
+        if enabled && valid_bits.is_none() {
+            println!("The graphics queue can't write timestamps, no GPU profile");
+        }
+
+        GpuProfiler {
+            enabled: enabled && valid_bits.is_some(),
+            timestamp_period: physical_device.properties().timestamp_period,
+            timestamp_mask: match valid_bits {
+                Some(bits) if bits < 64 => (1 << bits) - 1,
+                _ => u64::MAX,
+            },
+            queue,
+            frames: Vec::new(),
+            current: 0,
+            open: false,
+            totals: Vec::new(),
+            frame_total: 0.0,
+            frames_measured: 0,
+        }
+    }
+
+    // Call at the start of the command buffer for swapchain image `slot`, outside any
+    // render pass and after waiting on that image's fence.
+    pub fn begin_frame<L, A: CommandBufferAllocator>(&mut self, slot: usize, builder: &mut AutoCommandBufferBuilder<L, A>) {
+        if !self.enabled {
+            return;
+        }
+
+        while self.frames.len() <= slot {
+            let pool = QueryPool::new(self.queue.device().clone(), QueryPoolCreateInfo {
+                query_count: MAX_PASSES * 2,
+                ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+            }).unwrap();
+            self.frames.push(FrameQueries {
+                pool,
+                passes: Vec::new(),
+            });
+        }
+
+        self.read_results(slot);
+        self.current = slot;
+        self.open = false;
+
+        // the queries are reset before any of them is written again in this buffer
+        unsafe {
+            builder.reset_query_pool(self.frames[slot].pool.clone(), 0..MAX_PASSES * 2).unwrap();
+        }
+    }
+
+    // Starts timing the commands recorded from here until `end` as `name`.
+    pub fn begin<L, A: CommandBufferAllocator>(&mut self, name: &'static str, builder: &mut AutoCommandBufferBuilder<L, A>) {
+        if !self.enabled {
+            return;
+        }
+
+        assert!(!self.open, "`{}` began before the last pass ended", name);
+        let frame = &mut self.frames[self.current];
+        let index = frame.passes.len() as u32;
+        if index == MAX_PASSES {
+            panic!("More than {} passes to profile", MAX_PASSES);
+        }
+
+        frame.passes.push(name);
+        self.open = true;
+        self.write_timestamp(index * 2, builder);
+    }
+
+    pub fn end<L, A: CommandBufferAllocator>(&mut self, builder: &mut AutoCommandBufferBuilder<L, A>) {
+        if !self.enabled {
+            return;
+        }
+
+        assert!(self.open, "ended a pass that never began");
+        let index = self.frames[self.current].passes.len() as u32 - 1;
+
+        self.open = false;
+        self.write_timestamp(index * 2 + 1, builder);
+    }
+
+    fn write_timestamp<L, A: CommandBufferAllocator>(&self, query: u32, builder: &mut AutoCommandBufferBuilder<L, A>) {
+        // every query written has been reset by `begin_frame` earlier in the buffer
+        unsafe {
+            builder
+                .write_timestamp(self.frames[self.current].pool.clone(), query, PipelineStage::BottomOfPipe)
+                .unwrap();
+        }
+    }
+
+    // Adds what the last frame recorded into `slot` measured to the totals. A frame
+    // whose results aren't there, because it was never submitted, is left out.
+    fn read_results(&mut self, slot: usize) {
+        let frame = &mut self.frames[slot];
+        let passes = std::mem::take(&mut frame.passes);
+        if passes.is_empty() {
+            return;
+        }
+
+        let mut timestamps = vec![0u64; passes.len() * 2];
+        let available = frame.pool
+            .queries_range(0..timestamps.len() as u32)
+            .unwrap()
+            .get_results(&mut timestamps, QueryResultFlags::empty())
+            .unwrap();
+        if !available {
+            return;
+        }
+
+        let ms = |begin: u64, end: u64| {
+            (end.wrapping_sub(begin) & self.timestamp_mask) as f64 * self.timestamp_period as f64 / 1_000_000.0
+        };
+
+        for (name, pair) in passes.into_iter().zip(timestamps.chunks_exact(2)) {
+            let time = ms(pair[0], pair[1]);
+            match self.totals.iter_mut().find(|(total_name, _)| *total_name == name) {
+                Some((_, total)) => *total += time,
+                None => self.totals.push((name, time)),
+            }
+        }
+        self.frame_total += ms(timestamps[0], timestamps[timestamps.len() - 1]);
+        self.frames_measured += 1;
+
+        if self.frames_measured == REPORT_FRAMES {
+            self.report();
+        }
+    }
+
+    fn report(&mut self) {
+        let frames = self.frames_measured as f64;
+        let frame_time = self.frame_total / frames;
+        println!("GPU time per frame, averaged over {} frames: {:.3} ms", self.frames_measured, frame_time);
+
+        self.totals.sort_by(|a, b| b.1.total_cmp(&a.1));
+        for (name, total) in &self.totals {
+            let time = total / frames;
+            println!("  {:<12} {:>8.3} ms {:>5.1}%", name, time, time / frame_time * 100.0);
+        }
+
+        self.totals.clear();
+        self.frame_total = 0.0;
+        self.frames_measured = 0;
+    }
+}
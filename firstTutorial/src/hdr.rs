@@ -0,0 +1,69 @@
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::format::{Format, NumericType};
+use vulkano::swapchain::{ColorSpace, Surface};
+
+// How the tonemap pass has to encode the linear scene colour for the swapchain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputEncoding {
+    // Left to the format, normally an `_SRGB` one applying the sRGB transfer itself.
+    Sdr,
+    // An sRGB colour space in a UNORM format, the shader applies the transfer.
+    SdrUnorm,
+    // Extended linear sRGB in a float format, 1.0 = 80 nits.
+    ScRgb,
+    // BT.2020 primaries with the ST 2084 (PQ) transfer function.
+    Hdr10,
+}
+
+impl OutputEncoding {
+    // `output_params` for the tonemap shader, see `shaders/output.glsl`. `paper_white` is
+    // the luminance in nits that a shader output of 1.0 should be displayed at.
+    pub fn params(self, paper_white: f32) -> [f32; 4] {
+        match self {
+            OutputEncoding::Sdr => [0.0, 1.0, 0.0, 0.0],
+            OutputEncoding::SdrUnorm => [0.0, 1.0, 1.0, 0.0],
+            OutputEncoding::ScRgb => [1.0, paper_white / 80.0, 0.0, 0.0],
+            OutputEncoding::Hdr10 => [2.0, paper_white / 10000.0, 0.0, 0.0],
+        }
+    }
+}
+
+// Picks the swapchain format. With `hdr` set, an scRGB or HDR10 colour space is
+// taken when the surface reports one; otherwise, or when none is reported, an sRGB
+// non-linear `_SRGB` format wins over a UNORM one.
+pub fn choose_surface_format(physical_device: &PhysicalDevice, surface: &Surface, hdr: bool) -> (Format, ColorSpace, OutputEncoding) {
+    let formats = physical_device.surface_formats(surface, Default::default()).unwrap();
+
+    if hdr {
+        let preferred = [
+            (ColorSpace::ExtendedSrgbLinear, OutputEncoding::ScRgb),
+            (ColorSpace::Hdr10St2084, OutputEncoding::Hdr10),
+        ];
+
+        for (color_space, encoding) in preferred {
+            if let Some(&(format, _)) = formats.iter().find(|(_, c)| *c == color_space) {
+                println!("Using HDR swapchain: {:?} / {:?}", format, color_space);
+                return (format, color_space, encoding);
+            }
+        }
+
+        println!("No HDR colour space reported by the surface, falling back to sRGB");
+    }
+
+    let srgb_format = |format: Format| format.type_color() == Some(NumericType::SRGB);
+    let (format, color_space) = formats
+        .iter()
+        .copied()
+        .find(|&(f, c)| c == ColorSpace::SrgbNonLinear && srgb_format(f))
+        .or_else(|| formats.iter().copied().find(|(_, c)| *c == ColorSpace::SrgbNonLinear))
+        .unwrap_or(formats[0]);
+
+    // without an `_SRGB` format the shader has to encode, or the image comes out dark
+    let encoding = if color_space == ColorSpace::SrgbNonLinear && !srgb_format(format) {
+        println!("No sRGB swapchain format, encoding {:?} in the shader", format);
+        OutputEncoding::SdrUnorm
+    } else {
+        OutputEncoding::Sdr
+    };
+    (format, color_space, encoding)
+}
@@ -0,0 +1,81 @@
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+
+// Watches loaded asset files and reports the ones that change on disk, so they can be
+// loaded again. Editors often save by writing a new file and renaming it over the old
+// one, which a watch on the file itself would lose track of, so it's the containing
+// directory that gets watched and its events are matched by path.
+pub struct AssetWatcher {
+    watcher: RecommendedWatcher,
+    receiver: Receiver<notify::Result<notify::Event>>,
+    // canonical path to the path as the asset was loaded from
+    paths: HashMap<PathBuf, PathBuf>,
+}
+
+impl AssetWatcher {
+    pub fn new() -> notify::Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+
+        Ok(AssetWatcher {
+            watcher: notify::recommended_watcher(sender)?,
+            receiver,
+            paths: HashMap::new(),
+        })
+    }
+
+    pub fn watch(&mut self, path: &Path) {
+        let result = fs::canonicalize(path)
+            .map_err(notify::Error::io)
+            .and_then(|canonical| {
+                let dir = canonical.parent().unwrap_or(Path::new("/")).to_path_buf();
+                self.watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+                self.paths.insert(canonical, path.to_path_buf());
+                Ok(())
+            });
+
+        if let Err(e) = result {
+            eprintln!("Not watching {} for changes: {}", path.display(), e);
+        }
+    }
+
+    // Every watched path written to since the last call, once each, without blocking.
+    // Deleted files are reported here and left out, whatever was loaded from them
+    // stays in use.
+    pub fn changed(&self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+
+        for event in self.receiver.try_iter() {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    eprintln!("Asset watcher error: {}", e);
+                    continue;
+                }
+            };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)) {
+                continue;
+            }
+
+            for path in event.paths.iter().filter_map(|path| self.paths.get(path)) {
+                if !changed.contains(path) {
+                    changed.push(path.clone());
+                }
+            }
+        }
+
+        // a rename over the file shows up as a removal too, only what's still gone counts
+        changed.retain(|path| {
+            let exists = path.exists();
+            if !exists {
+                println!("{} was deleted, keeping the loaded copy", path.display());
+            }
+            exists
+        });
+
+        changed
+    }
+}
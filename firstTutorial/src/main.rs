@@ -1,36 +1,125 @@
-use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+mod cache;
+mod camera;
+mod caps;
+mod capture;
+mod clock;
+mod config;
+mod coords;
+mod debug;
+mod debug_view;
+mod depth;
+mod frame_limiter;
+mod fxaa;
+mod gpu_profiler;
+mod hdr;
+mod hot_reload;
+mod material;
+mod motion_blur;
+mod pass_flags;
+mod render_graph;
+mod safe_mode;
+mod sampler;
+mod scene;
+mod streaming;
+mod tonemap;
+mod velocity;
+mod volume;
+
+use vulkano::buffer::{BufferAccess, BufferUsage, CpuBufferPool};
+use vulkano::command_buffer::allocator::{CommandBufferAllocator, StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo};
 use vulkano::command_buffer::{AutoCommandBufferBuilder,
-CommandBufferUsage, RenderPassBeginInfo, SubpassContents};
+CommandBufferUsage, CopyImageInfo, RenderPassBeginInfo, RenderingAttachmentInfo, RenderingAttachmentResolveInfo, RenderingInfo, SubpassContents};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
 
-use vulkano::device::physical::PhysicalDeviceType;
-use vulkano::device::{Device, DeviceCreateInfo, DeviceExtensions, QueueCreateInfo};
-use vulkano::image::view::ImageView;
-use vulkano::image::{ImageAccess, SwapchainImage};
+use vulkano::device::physical::{PhysicalDevice, PhysicalDeviceType};
+use vulkano::device::{Device, DeviceCreateInfo, DeviceExtensions, Features, QueueCreateInfo};
+use vulkano::format::{ClearValue, Format};
+use vulkano::image::view::{ImageView, ImageViewAbstract};
+use vulkano::image::{AttachmentImage, ImageAccess, ImageUsage, SampleCount, SwapchainImage};
 use vulkano::instance::{Instance, InstanceCreateInfo};
-use vulkano::pipeline::graphics::viewport::Viewport;
-use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass};
-use vulkano::swapchain::{self, AcquireError, Swapchain, SwapchainCreateInfo, SwapchainCreationError, SwapchainPresentInfo,};
-use vulkano::sync::{self, FlushError, GpuFuture};
+use vulkano::memory::allocator::{MemoryUsage, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{ColorBlendState, ColorComponents};
+use vulkano::pipeline::graphics::depth_stencil::{CompareOp, DepthState, DepthStencilState};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::{DepthBias, DepthBiasState, PolygonMode, RasterizationState};
+use vulkano::pipeline::graphics::render_pass::{PipelineRenderPassType, PipelineRenderingCreateInfo};
+use vulkano::pipeline::graphics::vertex_input::BuffersDefinition;
+use vulkano::pipeline::graphics::viewport::{Scissor, Viewport, ViewportState};
+use vulkano::pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint, StateMode};
+use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, LoadOp, RenderPass, ResolveMode, StoreOp, Subpass};
+use vulkano::swapchain::{self, AcquireError, SurfaceCapabilities, Swapchain, SwapchainCreateInfo, SwapchainCreationError, SwapchainPresentInfo,};
+use vulkano::sync::future::FenceSignalFuture;
+use vulkano::sync::{self, FlushError, GpuFuture, Sharing};
 use vulkano::{Version, VulkanLibrary};
 
 use vulkano_win::VkSurfaceBuild;
 
-use winit::event::{Event, WindowEvent};
+use winit::event::{ElementState, Event, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ ControlFlow, EventLoop};
-use winit::window::{Window, WindowBuilder};
+use winit::window::{Fullscreen, Window, WindowBuilder};
+
+use cgmath::{Matrix4, Point3, Rad, SquareMatrix, Vector3, Vector4};
 
+use std::collections::HashMap;
+use std::fs;
+use std::mem::{self, size_of};
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
+
+use cache::ResourceCache;
+use capture::{ClipboardCapture, FrameDump, FrameRecorder};
+use camera::{Camera, CameraMode};
+use clock::AnimationClock;
+use config::{AntiAliasing, Config};
+use debug_view::DebugView;
+use depth::DepthMode;
+use frame_limiter::FrameLimiter;
+use gpu_profiler::GpuProfiler;
+use hot_reload::AssetWatcher;
+use material::{MaterialPipelines, MaterialVariant};
+use pass_flags::PassFlags;
+use render_graph::{PassKind, RenderGraph, SWAPCHAIN};
+use safe_mode::RunMarker;
+use sampler::SamplerCache;
+use scene::{Mesh, Node, Vertex};
+use streaming::{AssetStreamer, StreamedAsset};
+use velocity::MotionHistory;
+use volume::VolumeSettings;
 
 fn main() {
 
+    let mut config = Config::from_args();
+    if RunMarker::left_behind() {
+        config.make_safe();
+        println!("The last run didn't exit cleanly, starting in safe mode with optional features off");
+    }
+
     // instance
     let instance = {
         let library = VulkanLibrary::new().unwrap();
-        let extensions = vulkano_win::required_extensions(&library);
+        let mut extensions = vulkano_win::required_extensions(&library);
+        // only used to label objects, so take it when it's there
+        extensions.ext_debug_utils = library.supported_extensions().ext_debug_utils;
+        // HDR colour spaces are only reported with this one enabled
+        extensions.ext_swapchain_colorspace = (config.hdr || config.caps) && library.supported_extensions().ext_swapchain_colorspace;
+
+        // for finding out what went wrong last time
+        let validation = "VK_LAYER_KHRONOS_validation";
+        let enabled_layers = if config.safe_mode
+            && library.layer_properties().unwrap().any(|layer| layer.name() == validation)
+        {
+            vec![validation.to_owned()]
+        } else {
+            Vec::new()
+        };
 
         Instance::new(library,
             InstanceCreateInfo {
                 enabled_extensions: extensions,
+                enabled_layers,
                 enumerate_portability: true,
                 max_api_version: Some(Version::V1_1),
                 ..Default::default()
@@ -49,20 +138,24 @@ fn main() {
         ..DeviceExtensions::empty()
     };
 
-    let (physical_device, queue_family_index) = instance.enumerate_physical_devices().unwrap()
+    // a graphics family that can also present if there is one, otherwise any graphics
+    // family and a separate one to present from
+    let (physical_device, queue_family_index, present_family_index) = instance.enumerate_physical_devices().unwrap()
         .filter(|p| p.supported_extensions().contains(&device_extensions))
-        .fiter_map(
+        .filter_map(
             |p| {
-                p.queue_family_properties()
-                    .iter()
-                    .enumerate()
-                    .position(|i, q| {
-                        q.queue_flags.properties && p.surface(i as u32, &surface).unwrap_or(false)
-                    })
-                    .map(|i| (p, i as u32))
+                let families = p.queue_family_properties();
+                let presents = |i: usize| p.surface_support(i as u32, &surface).unwrap_or(false);
+                let graphics = || (0..families.len()).filter(|&i| families[i].queue_flags.graphics);
+
+                let (graphics_index, present_index) = match graphics().find(|&i| presents(i)) {
+                    Some(i) => (i, i),
+                    None => (graphics().next()?, (0..families.len()).find(|&i| presents(i))?),
+                };
+                Some((p, graphics_index as u32, present_index as u32))
             }
         )
-        .min_by_key(|p, _|{
+        .min_by_key(|(p, _, _)|{
             match p.properties().device_type {
                 PhysicalDeviceType::DiscreteGpu => 0,
                 PhysicalDeviceType::IntegratedGpu => 1,
@@ -74,80 +167,391 @@ fn main() {
         })
         .expect("No suitable physical device found.");
 
-    // Device
-    let (device, mut queues) = Device::new(physical_device, DeviceCreateInfo {
-        enabled_extensions: device_extensions,
-        queue_create_infos: vec![QueueCreateInfo {
+    if config.caps {
+        caps::print_surface_caps(&physical_device, &surface);
+        return;
+    }
+
+    // from here on a crash puts the next run in safe mode
+    let run_marker = RunMarker::create();
+
+    let separate_present = present_family_index != queue_family_index;
+    if separate_present {
+        println!("Presenting from queue family {}, rendering on {}", present_family_index, queue_family_index);
+    }
+
+    // a transfer-only family, if there is one, lets uploads run beside rendering
+    let transfer_family_index = physical_device.queue_family_properties()
+        .iter()
+        .enumerate()
+        .position(|(i, q)| q.queue_flags.transfer && !q.queue_flags.graphics && i as u32 != present_family_index)
+        .map(|i| i as u32);
+
+    // one queue per family; the priorities are hints for how the device schedules them
+    // against each other, presenting gets the same as rendering since it waits on it
+    let mut queue_create_infos = vec![QueueCreateInfo {
+        queues: vec![config.graphics_priority],
+        queue_family_index,
+        ..Default::default()
+    }];
+    if let Some(queue_family_index) = transfer_family_index {
+        queue_create_infos.push(QueueCreateInfo {
+            queues: vec![config.transfer_priority],
             queue_family_index,
             ..Default::default()
-        }],
+        });
+        println!("Queue priorities: graphics {}, transfer {}", config.graphics_priority, config.transfer_priority);
+    }
+    if separate_present {
+        queue_create_infos.push(QueueCreateInfo {
+            queues: vec![config.graphics_priority],
+            queue_family_index: present_family_index,
+            ..Default::default()
+        });
+    }
+
+    let samples = match config.aa {
+        AntiAliasing::Msaa => choose_sample_count(&physical_device, config.msaa),
+        AntiAliasing::None | AntiAliasing::Fxaa => SampleCount::Sample1,
+    };
+    // the passes the config leaves off are dropped from the graph
+    let mut render_graph = match &config.render_graph {
+        Some(path) => RenderGraph::from_ron(
+            &fs::read_to_string(path).unwrap_or_else(|e| panic!("Can't read {}: {}", path.display(), e)),
+        ),
+        None => RenderGraph::from_ron(render_graph::DEFAULT),
+    }.unwrap_or_else(|e| panic!("Invalid render graph: {}", e));
+    render_graph
+        .retain(|kind| match kind {
+            PassKind::MotionBlur => config.motion_blur,
+            PassKind::Fxaa => config.aa == AntiAliasing::Fxaa,
+            PassKind::Scene | PassKind::Tonemap => true,
+        })
+        .unwrap_or_else(|e| panic!("Invalid render graph without the passes switched off: {}", e));
+    let fxaa = render_graph.contains(PassKind::Fxaa);
+    let motion_blur = render_graph.contains(PassKind::MotionBlur);
+    if (config.aa == AntiAliasing::Fxaa && !fxaa) || (config.motion_blur && !motion_blur) {
+        println!("The render graph leaves out a pass the config asks for");
+    }
+    let depth = DepthMode::new(&physical_device, config.reversed_z);
+
+    let vertex_pulling = config.vertex_pulling
+        && physical_device.supported_extensions().khr_buffer_device_address
+        && physical_device.supported_features().buffer_device_address;
+    if config.vertex_pulling && !vertex_pulling {
+        println!("Buffer device addresses are not supported, using fixed-function vertex input");
+    }
+    // two model matrices and the address are more than the 128 bytes every device has
+    let pulled_push_fits = physical_device.properties().max_push_constants_size as usize
+        >= size_of::<pulled_vs::ty::PushConstants>();
+    if vertex_pulling && !pulled_push_fits {
+        println!("Vertex pulling's push constants don't fit, using fixed-function vertex input");
+    }
+    let vertex_pulling = vertex_pulling && pulled_push_fits;
+
+    let dynamic_rendering = config.dynamic_rendering
+        && physical_device.supported_extensions().khr_dynamic_rendering
+        && physical_device.supported_features().dynamic_rendering;
+    if config.dynamic_rendering && !dynamic_rendering {
+        println!("Dynamic rendering is not supported, using a render pass");
+    }
+
+    // line rasterization for the wireframe overlay
+    let wireframe_supported = physical_device.supported_features().fill_mode_non_solid;
+    if config.wireframe && !wireframe_supported {
+        println!("Line polygon mode is not supported, no wireframe overlay");
+    }
+    // the overlay's lines are as wide as the device allows, and 1 pixel without
+    // wide lines
+    let wide_lines = wireframe_supported && physical_device.supported_features().wide_lines;
+    let line_width_range = if wide_lines {
+        physical_device.properties().line_width_range
+    } else {
+        [1.0, 1.0]
+    };
+    if config.line_width != 1.0 && wireframe_supported && !wide_lines {
+        println!("Wide lines are not supported, drawing 1 pixel lines");
+    }
+
+    // per-sample shading only does anything with more than one sample
+    let enabled_features = Features {
+        sample_rate_shading: samples != SampleCount::Sample1
+            && config.sample_shading > 0.0
+            && physical_device.supported_features().sample_rate_shading,
+        sampler_anisotropy: physical_device.supported_features().sampler_anisotropy,
+        buffer_device_address: vertex_pulling,
+        dynamic_rendering,
+        fill_mode_non_solid: wireframe_supported,
+        wide_lines,
+        ..Features::empty()
+    };
+
+    // Device
+    let (device, mut queues) = Device::new(physical_device, DeviceCreateInfo {
+        enabled_extensions: DeviceExtensions {
+            // core from Vulkan 1.2, but the instance stops at 1.1
+            khr_buffer_device_address: vertex_pulling,
+            // the extension and what it depends on, again all core in 1.2 and up
+            khr_dynamic_rendering: dynamic_rendering,
+            khr_depth_stencil_resolve: dynamic_rendering,
+            khr_create_renderpass2: dynamic_rendering,
+            ..device_extensions
+        },
+        enabled_features,
+        queue_create_infos,
         ..Default::default()
     },).unwrap();
+    debug::set_name(&*device, "device");
 
     // Queues
     let queue = queues.next().unwrap();
+    debug::set_name(&*queue, "graphics queue");
+    // only the queues of their own are named, the rest are the graphics queue
+    let transfer_queue = match transfer_family_index {
+        Some(_) => {
+            let transfer_queue = queues.next().unwrap();
+            debug::set_name(&*transfer_queue, "transfer queue");
+            transfer_queue
+        }
+        None => queue.clone(),
+    };
+    let present_queue = if separate_present {
+        let present_queue = queues.next().unwrap();
+        debug::set_name(&*present_queue, "present queue");
+        present_queue
+    } else {
+        queue.clone()
+    };
 
     // Swapchains and Images
-    let (mut swapchain, images) = {
+    let (mut swapchain, mut images, output_encoding) = {
         let caps = device.physical_device().surface_capabilities(&surface, Default::default())
             .unwrap();
 
         let usage = caps.supported_usage_flags;
         let alpha = caps.supported_composite_alpha.iter().next().unwrap();
 
-        let image_format = Some(device.physical_device().surface_formats(&surface, Default::default()).unwrap()[0].0,);
+        let (image_format, image_color_space, output_encoding) =
+            hdr::choose_surface_format(device.physical_device(), &surface, config.hdr);
 
         let window = surface.object().unwrap().downcast_ref::<Window>().unwrap();
-        let image_extent: [u32; 2] = window.inner_size().into();
+        let image_extent = swapchain_extent(&caps, window.inner_size().into());
 
-        Swapchain::new(
+        let (swapchain, images) = Swapchain::new(
             device.clone(),
             surface.clone(),
             SwapchainCreateInfo {
                 min_image_count: caps.min_image_count,
-                image_format,
+                image_format: Some(image_format),
+                image_color_space,
                 image_extent,
                 image_usage: usage,
+                // like streamed uploads (see `AssetStreamer`), concurrent sharing stands in
+                // for the ownership transfer vulkano can't record
+                image_sharing: if separate_present {
+                    Sharing::Concurrent(vec![queue_family_index, present_family_index].into())
+                } else {
+                    Sharing::Exclusive
+                },
                 composite_alpha: alpha,
                 ..Default::default()
             }
-        ).unwrap()
+        ).unwrap();
+
+        (swapchain, images, output_encoding)
     };
+    debug::set_name(&*swapchain, "swapchain");
 
     // Allocators
-    let command_buffer_allocator = StandardCommandBufferAllocator::new(device.clone(), Default::default());
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+    let descriptor_set_allocator = StandardDescriptorSetAllocator::new(device.clone());
+    // A frame's command buffer lives in its fence future in `fences`. Replacing that
+    // future when the image comes round again hands the buffer back to the pool,
+    // which resets it for reuse instead of allocating. Sizing the pool to the number
+    // of frames in flight means steady state never has to grow it.
+    let command_buffer_allocator = StandardCommandBufferAllocator::new(
+        device.clone(),
+        StandardCommandBufferAllocatorCreateInfo {
+            primary_buffer_count: images.len(),
+            secondary_buffer_count: 0,
+            ..Default::default()
+        },
+    );
 
-    // Shaders
+    // Renderpass, none with dynamic rendering
+    let render_pass = (!dynamic_rendering).then(|| {
+        let render_pass = create_render_pass(device.clone(), swapchain.image_format(), depth.format, samples);
+        debug::set_name(&*render_pass, "main render pass");
+        render_pass
+    });
+    println!("Rendering with {}", if dynamic_rendering { "dynamic rendering" } else { "a render pass" });
+    let scene_target = pipeline_target(&render_pass, 0, &[tonemap::HDR_FORMAT, velocity::FORMAT], Some(depth.format));
+    // FXAA and motion blur get passes of their own, they read neighbouring pixels of
+    // the tonemapped image
+    let post_render_pass = (!render_graph.post_passes().is_empty() && render_pass.is_some()).then(|| {
+        let render_pass = create_post_render_pass(device.clone(), swapchain.image_format());
+        debug::set_name(&*render_pass, "post render pass");
+        render_pass
+    });
+    let target_settings = TargetSettings {
+        render_pass: render_pass.clone(),
+        post_render_pass: post_render_pass.clone(),
+        render_graph: render_graph.clone(),
+        samples,
+        depth_format: depth.format,
+    };
 
-    // Renderpass
-    let render_pass = vulkano::single_pass_renderpass!(device.clone(),
-        attachments: {
-            color: {
-                load: Clear,
-                store: Store,
-                format: swapchain.image_format(),
-                samples: 1,
-            }
-        },
-        pass: {
-            color: [color],
-            depth_stencil: {}
+    // Graphics Pipeline
+    // running the shader per sample keeps thin specular highlights from shimmering
+    let sample_shading = device.enabled_features().sample_rate_shading.then_some(config.sample_shading);
+    let build_mesh_pipeline = {
+        let device = device.clone();
+        let scene_target = scene_target.clone();
+        move |pass: MeshPass, name: &str| {
+            let pipeline = create_mesh_pipeline(device.clone(), scene_target.clone(), samples, depth, sample_shading, pass, vertex_pulling);
+            debug::set_name(&*pipeline, name);
+            pipeline
         }
-    ).unwrap();
+    };
+    let depth_pipeline = build_mesh_pipeline(MeshPass::DepthOnly, "depth prepass pipeline");
+    let wireframe_pipeline = wireframe_supported.then(|| build_mesh_pipeline(MeshPass::Wireframe, "wireframe pipeline"));
+    // the lit pass gets a pipeline per material variant, it and the overdraw pass
+    // with and without the prepass; the starting ones are built now so the first
+    // frame doesn't
+    let mut mesh_pipelines = MaterialPipelines::new(move |pass: MeshPass| {
+        debug::log(format_args!("building the {:?} pipeline", pass));
+        build_mesh_pipeline(pass, &format!("{:?} mesh pipeline", pass))
+    });
+    let mut material = MaterialVariant::default();
+    for prepassed in [false, true] {
+        mesh_pipelines.get(MeshPass::Lit { prepassed, variant: material });
+        mesh_pipelines.get(MeshPass::Overdraw { prepassed });
+    }
+    println!("Vertex input: {}", if vertex_pulling { "pulled by buffer device address" } else { "fixed-function bindings" });
+    println!("Depth: {:?}{}", depth.format, if depth.reversed { ", reversed-Z" } else { "" });
+    match config.aa {
+        AntiAliasing::Fxaa => println!("Antialiasing: FXAA ({:?})", config.fxaa_quality),
+        aa => println!("Antialiasing: {:?}", aa),
+    }
+    if motion_blur {
+        println!("Motion blur: strength {}, up to {} samples", config.motion_blur_strength, config.motion_blur_samples);
+    }
+    println!(
+        "MSAA: {:?}, sample shading: {}",
+        samples,
+        if device.enabled_features().sample_rate_shading { "on" } else { "off" },
+    );
+
+    let tonemap_pipeline = tonemap::create_pipeline(
+        device.clone(),
+        pipeline_target(&render_pass, 1, &[swapchain.image_format()], None),
+        dynamic_rendering,
+    );
+    debug::set_name(&*tonemap_pipeline, "tonemap pipeline");
+    let fxaa_pipeline = fxaa.then(|| {
+        let pipeline = fxaa::create_pipeline(device.clone(), pipeline_target(&post_render_pass, 0, &[swapchain.image_format()], None));
+        debug::set_name(&*pipeline, "fxaa pipeline");
+        pipeline
+    });
+    let motion_blur_pipeline = motion_blur.then(|| {
+        let pipeline = motion_blur::create_pipeline(device.clone(), pipeline_target(&post_render_pass, 0, &[swapchain.image_format()], None));
+        debug::set_name(&*pipeline, "motion blur pipeline");
+        pipeline
+    });
 
-    // Graphics Pipeline
     let mut viewport = Viewport {
         origin: [0.0, 0.0],
         dimensions: [0.0, 0.0],
         depth_range: 0.0..1.0,
     };
 
-    // Framebuffers
-    let mut framebuffers = window_size_dependent_setup(&images, render_pass.clone(), &mut viewport);
+    // Uniforms
+    let globals_buffer = CpuBufferPool::<vs::ty::Globals>::new(
+        memory_allocator.clone(),
+        BufferUsage {
+            uniform_buffer: true,
+            ..BufferUsage::empty()
+        },
+        MemoryUsage::Upload,
+    );
+
+    // Scene
+    let cache = Arc::new(ResourceCache::new());
+    let cube = cache.mesh(Path::new("builtin/cube"), || Mesh::cube(&memory_allocator));
+    debug::set_name(&**cube.vertex_buffer.inner().buffer, "cube vertices");
+    debug::set_name(&**cube.index_buffer.inner().buffer, "cube indices");
+    let mut scene = articulated_arm(&cube);
+    let mut camera = Camera::new(Point3::new(4.0, 3.0, 5.0), Point3::new(0.0, 1.0, 0.0));
+    camera.zoom_speed = config.zoom_speed;
+    camera.reversed_z = depth.reversed;
+    let mut side_camera = Camera::new(Point3::new(-6.0, 5.0, 0.0), Point3::new(0.0, 1.0, 0.0));
+    side_camera.reversed_z = depth.reversed;
+    let mut clock = AnimationClock::new();
+    let mut frame_limiter = FrameLimiter::new(config.fps_cap);
+    let mut gpu_profiler = GpuProfiler::new(queue.clone(), config.gpu_profile);
+    let mut motion_history = MotionHistory::default();
+
+    // Recording
+    let mut recorder = config.record.clone().map(|dir| {
+        FrameRecorder::new(dir.clone(), config.record_frames)
+            .unwrap_or_else(|e| panic!("Can't record to {}: {}", dir.display(), e))
+    });
+    // Ctrl+C puts the next frame on the clipboard
+    let mut clipboard_capture = ClipboardCapture::default();
+    let mut capture_requested = false;
+    // F9 saves every target of the next frame
+    let mut dump_requested = false;
+
+    let samplers = SamplerCache::new(device.clone());
+
+    // Volume
+    let streamer = AssetStreamer::new(transfer_queue, memory_allocator.clone(), cache.clone(), config.fence_timeout);
+    // filled in by the streamer; a plain box stands in until then
+    let mut volume = None;
+    // the cache generation of `volume`, older uploads arriving later are ignored
+    let mut volume_generation = 0;
+    if let Some(path) = &config.volume {
+        streamer.load_volume(path.clone(), config.volume_size);
+    }
+    // edits to the volume file are streamed in again, replacing it once uploaded
+    let mut asset_watcher = AssetWatcher::new()
+        .map_err(|e| eprintln!("Assets won't reload on change: {}", e))
+        .ok();
+    if let (Some(watcher), Some(path)) = (&mut asset_watcher, &config.volume) {
+        watcher.watch(path);
+    }
+    let volume_pipeline = volume::create_pipeline(device.clone(), scene_target, samples, depth.closer());
+    debug::set_name(&*volume_pipeline, "volume pipeline");
+    let volume_transform = Matrix4::from_translation(Vector3::new(2.5, 1.0, 0.0)) * Matrix4::from_scale(2.0);
+    let mut volume_settings = VolumeSettings::default();
+
+    // Input
+    let mut scroll_lines = 0.0;
+    let mut dragging = false;
+    let mut modifiers = ModifiersState::empty();
+    let mut cursor_position: Option<[f64; 2]> = None;
+    let mut split_screen = false;
+    let mut debug_view = DebugView::default();
+    let mut pass_flags = PassFlags::all();
+    let mut depth_prepass = config.depth_prepass;
+    let mut wireframe = config.wireframe && wireframe_supported;
+    let mut line_width = config.line_width.clamp(line_width_range[0], line_width_range[1]);
+    // inner size to go back to when leaving fullscreen, in logical pixels so it
+    // comes out the same on a monitor with another scale factor
+    let mut windowed_size = None;
+    // physical pixels per logical pixel; input deltas are divided by it so dragging
+    // and touchpad scrolling feel the same on every monitor
+    let mut scale_factor = surface.object().unwrap().downcast_ref::<Window>().unwrap().scale_factor();
+
+    // Render targets
+    let mut targets = window_size_dependent_setup(&images, &target_settings, &memory_allocator, &mut viewport);
 
     let mut recreate_swapchain = false;
 
-    let mut previous_frame_end = Some(Box::new(sync::now(device.clone())) as Box<dyn GpuFuture>);
+    // one fence per swapchain image, so we only block on the frame that last used
+    // the image we are about to render to
+    let mut fences: Vec<Option<Arc<FenceSignalFuture<Box<dyn GpuFuture>>>>> = vec![None; images.len()];
+    let mut previous_fence_index = 0;
 
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent {
@@ -162,14 +566,198 @@ fn main() {
         } => {
             recreate_swapchain = true;
         }
+        // moving to a monitor with another scale factor can leave the surface
+        // suboptimal without the extent changing, so no Resized is guaranteed
+        Event::WindowEvent {
+            event: WindowEvent::ScaleFactorChanged { scale_factor: new_scale_factor, .. },
+            ..
+        } => {
+            debug::log(format_args!("scale factor changed to {}, recreating the swapchain", new_scale_factor));
+            scale_factor = new_scale_factor;
+            recreate_swapchain = true;
+        }
+        Event::WindowEvent {
+            event: WindowEvent::MouseWheel { delta, .. },
+            ..
+        } => {
+            scroll_lines += match delta {
+                MouseScrollDelta::LineDelta(_, y) => y,
+                // touchpads report pixels, roughly 20 logical ones make up a line
+                MouseScrollDelta::PixelDelta(position) => (position.y / scale_factor) as f32 / 20.0,
+            };
+        }
+        Event::WindowEvent {
+            event: WindowEvent::ModifiersChanged(new_modifiers),
+            ..
+        } => {
+            modifiers = new_modifiers;
+        }
+        Event::WindowEvent {
+            event: WindowEvent::MouseInput { state, button: MouseButton::Left, .. },
+            ..
+        } => {
+            dragging = state == ElementState::Pressed;
+        }
+        Event::WindowEvent {
+            event: WindowEvent::CursorMoved { position, .. },
+            ..
+        } => {
+            if let Some([x, y]) = cursor_position {
+                if dragging && camera.mode == CameraMode::Orbit {
+                    let [dx, dy] = [(position.x - x) / scale_factor, (position.y - y) / scale_factor];
+                    camera.orbit(dx as f32 * 0.01, dy as f32 * 0.01);
+                }
+            }
+            cursor_position = Some([position.x, position.y]);
+        }
+        Event::WindowEvent {
+            event: WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(key),
+                    ..
+                },
+                ..
+            },
+            ..
+        } => match key {
+            VirtualKeyCode::Escape => {
+                *control_flow = ControlFlow::Exit;
+            }
+            VirtualKeyCode::C if modifiers.ctrl() => {
+                capture_requested = true;
+            }
+            VirtualKeyCode::C => {
+                camera.toggle_mode();
+                println!("Camera mode: {:?}", camera.mode);
+            }
+            VirtualKeyCode::F9 => {
+                dump_requested = true;
+            }
+            VirtualKeyCode::H => {
+                material.specular = !material.specular;
+                println!("Material: {:?}", material);
+            }
+            VirtualKeyCode::F => {
+                material.flat_shading = !material.flat_shading;
+                println!("Material: {:?}", material);
+            }
+            VirtualKeyCode::V => {
+                split_screen = !split_screen;
+            }
+            VirtualKeyCode::P => {
+                clock.toggle_pause();
+                println!("Animation {}", if clock.is_paused() { "paused" } else { "resumed" });
+            }
+            VirtualKeyCode::Period => clock.step(),
+            VirtualKeyCode::W => {
+                if wireframe_pipeline.is_some() {
+                    wireframe = !wireframe;
+                    println!("Wireframe overlay {}", if wireframe { "on" } else { "off" });
+                } else {
+                    println!("Line polygon mode is not supported, no wireframe overlay");
+                }
+            }
+            VirtualKeyCode::Comma | VirtualKeyCode::Slash => {
+                let step = if key == VirtualKeyCode::Slash { 1.0 } else { -1.0 };
+                line_width = (line_width + step).clamp(line_width_range[0], line_width_range[1]);
+                println!("Wireframe line width: {}", line_width);
+            }
+            VirtualKeyCode::Z => {
+                depth_prepass = !depth_prepass;
+                // the overdraw view (6) shows the difference: one layer with it on
+                println!("Depth prepass {}", if depth_prepass { "on" } else { "off" });
+            }
+            key if PassFlags::from_key(key).is_some() => {
+                pass_flags.toggle(PassFlags::from_key(key).unwrap());
+                println!("Passes: {}", pass_flags);
+            }
+            key if DebugView::from_key(key).is_some() => {
+                debug_view = DebugView::from_key(key).unwrap();
+                println!("Debug view: {:?}", debug_view);
+            }
+            VirtualKeyCode::LBracket | VirtualKeyCode::RBracket => {
+                volume_settings.steps = if key == VirtualKeyCode::RBracket {
+                    (volume_settings.steps * 2).min(1024)
+                } else {
+                    (volume_settings.steps / 2).max(8)
+                };
+                println!("Volume steps: {}", volume_settings.steps);
+            }
+            VirtualKeyCode::Minus | VirtualKeyCode::Equals => {
+                volume_settings.opacity *= if key == VirtualKeyCode::Equals { 1.25 } else { 0.8 };
+                println!("Volume opacity: {:.2}", volume_settings.opacity);
+            }
+            VirtualKeyCode::N | VirtualKeyCode::M | VirtualKeyCode::K | VirtualKeyCode::L => {
+                // N and M halve and double the near plane, K and L the far one
+                let (near, far) = camera.clip_planes();
+                let (near, far) = match key {
+                    VirtualKeyCode::N => (near * 0.5, far),
+                    VirtualKeyCode::M => (near * 2.0, far),
+                    VirtualKeyCode::K => (near, far * 0.5),
+                    _ => (near, far * 2.0),
+                };
+                camera.set_clip_planes(near, far);
+                side_camera.set_clip_planes(near, far);
+
+                // the ratio is what decides how much depth precision there is to go round
+                let (near, far) = camera.clip_planes();
+                println!("Clip planes: near {}, far {} (far/near {:.0}, {:?})", near, far, far / near, depth.format);
+            }
+            VirtualKeyCode::Semicolon | VirtualKeyCode::Apostrophe => {
+                let step = if key == VirtualKeyCode::Apostrophe { 0.25 } else { -0.25 };
+                samplers.set_lod_bias(samplers.lod_bias() + step);
+                println!("Texture LOD bias: {:+.2}", samplers.lod_bias());
+            }
+            VirtualKeyCode::Back => {
+                cache.clear();
+                println!("Resource cache cleared ({:?})", cache.stats());
+            }
+            VirtualKeyCode::F11 => {
+                let window = surface.object().unwrap().downcast_ref::<Window>().unwrap();
+
+                if window.fullscreen().is_some() {
+                    window.set_fullscreen(None);
+                    if let Some(size) = windowed_size.take() {
+                        window.set_inner_size(size);
+                    }
+                } else {
+                    windowed_size = Some(window.inner_size().to_logical::<f64>(scale_factor));
+                    // stay on whichever monitor the window currently sits on
+                    window.set_fullscreen(Some(Fullscreen::Borderless(window.current_monitor())));
+                }
+
+                // not every platform sends a Resized event for this
+                recreate_swapchain = true;
+            }
+            _ => {}
+        },
         Event::RedrawEventsCleared => {
+            // an exit request still lets the rest of this iteration run, don't queue more work
+            if let ControlFlow::ExitWithCode(_) = *control_flow {
+                return;
+            }
+
             // do our render operations here
+            let window = surface.object().unwrap().downcast_ref::<Window>().unwrap();
+            // physical pixels, so the swapchain matches the display 1:1 at any scale factor
+            let image_extent: [u32; 2] = window.inner_size().into();
+
+            if image_extent.contains(&0) {
+                return;
+            }
+
             if recreate_swapchain {
-                let window = surface.object().unwrap().downcast_ref::<Window>().unwrap();
-                let image_extent: [u32; 2] = window.inner_size().into();
+                // the image count may change, so retire every frame still in flight first
+                for fence in fences.iter().flatten() {
+                    wait_for_frame(fence, config.fence_timeout);
+                }
+
+                let caps = device.physical_device().surface_capabilities(&surface, Default::default())
+                    .unwrap();
 
                 let (new_swapchain, new_images) = match swapchain.recreate(SwapchainCreateInfo {
-                    image_extent,
+                    image_extent: swapchain_extent(&caps, image_extent),
                     ..swapchain.create_info()
                 }) {
                     Ok(r) => r,
@@ -178,31 +766,1347 @@ fn main() {
                 };
 
                 swapchain = new_swapchain;
-                framebuffers =
-                    window_size_dependent_setup(&new_images, render_pass.clone(), &mut viewport);
+                debug::set_name(&*swapchain, "swapchain");
+                targets = window_size_dependent_setup(&new_images, &target_settings, &memory_allocator, &mut viewport);
+                fences = vec![None; new_images.len()];
+                images = new_images;
+                previous_fence_index = 0;
                 recreate_swapchain = false;
             }
+
+            let (image_index, suboptimal, acquire_future) =
+                match swapchain::acquire_next_image(swapchain.clone(), Some(config.fence_timeout)) {
+                    Ok(r) => r,
+                    Err(AcquireError::OutOfDate) => {
+                        recreate_swapchain = true;
+                        return;
+                    }
+                    Err(AcquireError::Timeout) | Err(AcquireError::DeviceLost) => {
+                        gpu_hang("acquiring a swapchain image", config.fence_timeout)
+                    }
+                    Err(e) => panic!("Failed to acquire next image: {:?}", e),
+                };
+
+            // still render and present this image, the swapchain is recreated next frame
+            if suboptimal {
+                debug::log(format_args!("swapchain suboptimal on acquire, recreating next frame"));
+                recreate_swapchain = true;
+            }
+
+            if let Some(fence) = &fences[image_index as usize] {
+                wait_for_frame(fence, config.fence_timeout);
+            }
+
+            for path in asset_watcher.iter().flat_map(AssetWatcher::changed) {
+                println!("Reloading {}", path.display());
+                streamer.reload_volume(path, config.volume_size);
+            }
+
+            for asset in streamer.poll() {
+                match asset {
+                    // a reload can finish before the load it replaces
+                    StreamedAsset::Volume(texture, generation) if generation >= volume_generation => {
+                        volume = Some(texture);
+                        volume_generation = generation;
+                    }
+                    StreamedAsset::Volume(..) => {}
+                }
+            }
+
+            // a recording advances one video frame per rendered frame, however slow
+            let time = match recorder {
+                Some(_) => clock.advance(1.0 / config.record_fps as f32),
+                None => clock.tick(),
+            };
+            // swinging the parent joints drags everything below them along
+            animate_arm(&mut scene, time);
+
+            if scroll_lines != 0.0 {
+                camera.zoom(scroll_lines);
+                scroll_lines = 0.0;
+            }
+
+            let mut builder = AutoCommandBufferBuilder::primary(
+                &command_buffer_allocator,
+                queue.queue_family_index(),
+                CommandBufferUsage::OneTimeSubmit,
+            ).unwrap();
+            gpu_profiler.begin_frame(image_index as usize, &mut builder);
+
+            gpu_profiler.begin("scene", &mut builder);
+            if render_pass.is_some() {
+                builder
+                    .begin_render_pass(
+                        RenderPassBeginInfo {
+                            clear_values: clear_values(samples, depth),
+                            ..RenderPassBeginInfo::framebuffer(targets.framebuffers[image_index as usize].clone())
+                        },
+                        SubpassContents::Inline,
+                    )
+                    .unwrap();
+            } else {
+                builder.begin_rendering(scene_rendering_info(&targets, depth)).unwrap();
+            }
+
+            // the interactive camera on the left, the fixed comparison camera on the right
+            let views = if split_screen { vec![&camera, &side_camera] } else { vec![&camera] };
+            let view_rects = split_viewports(&viewport, views.len() as u32);
+
+            let mut scene_list = scene.draw_list();
+            if config.volume.is_some() && volume.is_none() {
+                scene_list.push((volume_transform, &cube));
+            }
+            let draw_list = motion_history.with_previous(&scene_list);
+            let mut view_projs = Vec::new();
+
+            for (view_index, (view_camera, (view_viewport, scissor))) in views.into_iter().zip(view_rects).enumerate() {
+                let globals = {
+                    let aspect = view_viewport.dimensions[0] / view_viewport.dimensions[1];
+                    let view = view_camera.view();
+                    let proj = view_camera.projection(aspect);
+                    view_projs.push(proj * view);
+
+                    globals_buffer.from_data(vs::ty::Globals {
+                        view: view.into(),
+                        proj: proj.into(),
+                        prev_view_proj: motion_history.previous_view_proj(view_index, proj * view).into(),
+                        line_color: WIREFRAME_COLOR,
+                        viewport_scale: [
+                            view_viewport.dimensions[0] / viewport.dimensions[0],
+                            view_viewport.dimensions[1] / viewport.dimensions[1],
+                        ],
+                        debug_view: debug_view as i32,
+                    }).unwrap()
+                };
+
+                builder
+                    .set_viewport(0, [view_viewport])
+                    .set_scissor(0, [scissor]);
+
+                let mesh_pipeline = mesh_pipelines.get(if debug_view == DebugView::Overdraw {
+                    MeshPass::Overdraw { prepassed: depth_prepass }
+                } else {
+                    MeshPass::Lit { prepassed: depth_prepass, variant: material }
+                });
+
+                if depth_prepass {
+                    draw_meshes(&mut builder, &descriptor_set_allocator, &depth_pipeline, globals.clone(), &draw_list, vertex_pulling);
+                }
+                draw_meshes(&mut builder, &descriptor_set_allocator, &mesh_pipeline, globals.clone(), &draw_list, vertex_pulling);
+                // the overdraw view counts surface layers, lines would only add noise
+                if let (Some(wireframe_pipeline), true) = (&wireframe_pipeline, wireframe && debug_view != DebugView::Overdraw) {
+                    builder.set_line_width(line_width);
+                    draw_meshes(&mut builder, &descriptor_set_allocator, wireframe_pipeline, globals.clone(), &draw_list, vertex_pulling);
+                }
+
+                // blended over the opaque scene, so it has to come last; the debug views
+                // only cover meshes
+                if let (Some(texture), false) = (&volume, debug_view.is_debug()) {
+                    let eye_object: Vector4<f32> = volume_transform.invert().unwrap() * view_camera.eye.to_homogeneous();
+                    let layout = volume_pipeline.layout().set_layouts().get(0).unwrap();
+                    let set = PersistentDescriptorSet::new(
+                        &descriptor_set_allocator,
+                        layout.clone(),
+                        [
+                            WriteDescriptorSet::buffer(0, globals),
+                            WriteDescriptorSet::image_view_sampler(1, texture.clone(), samplers.get(volume::SAMPLER)),
+                        ],
+                    ).unwrap();
+
+                    builder
+                        .bind_pipeline_graphics(volume_pipeline.clone())
+                        .bind_descriptor_sets(PipelineBindPoint::Graphics, volume_pipeline.layout().clone(), 0, set)
+                        .push_constants(volume_pipeline.layout().clone(), 0, volume::vs::ty::VolumePush {
+                            model: volume_transform.into(),
+                            eye_object: eye_object.into(),
+                            transfer: volume_settings.transfer(),
+                            steps: volume_settings.steps as i32,
+                        })
+                        .bind_vertex_buffers(0, cube.vertex_buffer.clone())
+                        .bind_index_buffer(cube.index_buffer.clone())
+                        .draw_indexed(cube.index_count, 1, 0, 0, 0)
+                        .unwrap();
+                }
+            }
+
+            motion_history.store(&scene_list, view_projs);
+            gpu_profiler.end(&mut builder);
+
+            // the scene's writes to the HDR target are made visible by the subpass
+            // dependency, or on the dynamic path by the barrier vulkano inserts between
+            // the two rendering scopes
+            let hdr_input = if render_pass.is_some() {
+                builder.next_subpass(SubpassContents::Inline).unwrap();
+                WriteDescriptorSet::image_view(0, targets.hdr.clone())
+            } else {
+                builder
+                    .end_rendering()
+                    .unwrap()
+                    .begin_rendering(color_rendering_info(targets.image(&render_graph.tonemap().outputs[0], image_index as usize)))
+                    .unwrap();
+                WriteDescriptorSet::image_view_sampler(0, targets.hdr.clone(), samplers.get(tonemap::SAMPLER))
+            };
+
+            gpu_profiler.begin("tonemap", &mut builder);
+            let tonemap_set = PersistentDescriptorSet::new(
+                &descriptor_set_allocator,
+                tonemap_pipeline.layout().set_layouts().get(0).unwrap().clone(),
+                [hdr_input],
+            ).unwrap();
+
+            builder
+                .set_viewport(0, [viewport.clone()])
+                .set_scissor(0, [Scissor::irrelevant()])
+                .bind_pipeline_graphics(tonemap_pipeline.clone())
+                .bind_descriptor_sets(PipelineBindPoint::Graphics, tonemap_pipeline.layout().clone(), 0, tonemap_set)
+                .push_constants(tonemap_pipeline.layout().clone(), 0, tonemap::fs::ty::TonemapPush {
+                    output_params: output_encoding.params(config.paper_white),
+                    exposure: config.exposure,
+                    bypass: (debug_view.is_debug() || !pass_flags.contains(PassFlags::TONEMAP)) as i32,
+                })
+                .draw(3, 1, 0, 0)
+                .unwrap();
+
+            if render_pass.is_some() {
+                builder.end_render_pass().unwrap();
+            } else {
+                builder.end_rendering().unwrap();
+            }
+            gpu_profiler.end(&mut builder);
+
+            // The passes after tonemapping, in the order the render graph runs them. One
+            // switched off with its F key is skipped, whatever reads its output reads its
+            // colour input instead, and if that was the swapchain image the colour is
+            // copied there at the end.
+            let mut forwarded: HashMap<&str, Arc<dyn ImageViewAbstract>> = HashMap::new();
+            let mut unpresented = None;
+
+            for (pass_index, pass) in render_graph.post_passes().iter().enumerate() {
+                let inputs = pass.inputs
+                    .iter()
+                    .map(|name| forwarded.get(name.as_str()).cloned().unwrap_or_else(|| targets.image(name, image_index as usize)))
+                    .collect::<Vec<_>>();
+                let output = pass.outputs[0].as_str();
+
+                let (pipeline, flag, sampler) = match pass.kind {
+                    PassKind::MotionBlur => (&motion_blur_pipeline, PassFlags::MOTION_BLUR, motion_blur::SAMPLER),
+                    PassKind::Fxaa => (&fxaa_pipeline, PassFlags::FXAA, fxaa::SAMPLER),
+                    PassKind::Scene | PassKind::Tonemap => unreachable!("the render graph only has these first"),
+                };
+                if !pass_flags.contains(flag) {
+                    if output == SWAPCHAIN {
+                        unpresented = Some(inputs[0].clone());
+                    } else {
+                        forwarded.insert(output, inputs[0].clone());
+                    }
+                    continue;
+                }
+                // built for every kind of pass in the graph
+                let pipeline = pipeline.as_ref().unwrap();
+
+                gpu_profiler.begin(pass.kind.label(), &mut builder);
+                begin_post_pass(
+                    &mut builder,
+                    targets.post_framebuffers.get(pass_index).map(|framebuffers| &framebuffers[image_index as usize]),
+                    targets.image(output, image_index as usize),
+                );
+
+                let set = PersistentDescriptorSet::new(
+                    &descriptor_set_allocator,
+                    pipeline.layout().set_layouts().get(0).unwrap().clone(),
+                    inputs.iter().enumerate().map(|(binding, input)| {
+                        WriteDescriptorSet::image_view_sampler(binding as u32, input.clone(), samplers.get(sampler))
+                    }),
+                ).unwrap();
+
+                builder
+                    .set_viewport(0, [viewport.clone()])
+                    .set_scissor(0, [Scissor::irrelevant()])
+                    .bind_pipeline_graphics(pipeline.clone())
+                    .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 0, set);
+
+                let extent = inputs[0].image().dimensions().width_height();
+                if pass.kind == PassKind::MotionBlur {
+                    // the debug views stay sharp
+                    let strength = if debug_view.is_debug() { 0.0 } else { config.motion_blur_strength };
+                    builder.push_constants(
+                        pipeline.layout().clone(),
+                        0,
+                        motion_blur::push_constants(extent, strength, config.motion_blur_samples),
+                    );
+                } else {
+                    builder.push_constants(pipeline.layout().clone(), 0, config.fxaa_quality.push_constants(extent));
+                }
+                builder.draw(3, 1, 0, 0).unwrap();
+
+                if render_pass.is_some() {
+                    builder.end_render_pass().unwrap();
+                } else {
+                    builder.end_rendering().unwrap();
+                }
+                gpu_profiler.end(&mut builder);
+            }
+
+            if let Some(result) = unpresented {
+                gpu_profiler.begin("copy", &mut builder);
+                builder
+                    .copy_image(CopyImageInfo::images(result.image(), images[image_index as usize].clone()))
+                    .unwrap();
+                gpu_profiler.end(&mut builder);
+            }
+
+            if let Some(recorder) = &mut recorder {
+                if let Err(e) = recorder.copy_frame(&memory_allocator, images[image_index as usize].clone(), &mut builder) {
+                    eprintln!("Can't record, {}, stopping", e);
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+            let mut capturing = mem::take(&mut capture_requested);
+            if capturing {
+                if let Err(e) = clipboard_capture.copy_frame(&memory_allocator, images[image_index as usize].clone(), &mut builder) {
+                    println!("Capturing is unavailable, {}", e);
+                    capturing = false;
+                }
+            }
+            let mut frame_dump = None;
+            if mem::take(&mut dump_requested) {
+                match FrameDump::new() {
+                    Ok(dump) => frame_dump = Some(dump),
+                    Err(e) => eprintln!("Can't create a frame dump directory, not dumping: {}", e),
+                }
+            }
+            if let Some(frame_dump) = &mut frame_dump {
+                for (name, image) in &targets.named {
+                    // on the render pass path the HDR target never leaves the tile
+                    if render_pass.is_some() && *name == render_graph.scene().outputs[0] {
+                        println!("Not dumping {}, with a render pass it's a transient input attachment", name);
+                        continue;
+                    }
+                    frame_dump.copy(name, &memory_allocator, image.image().clone(), &mut builder);
+                }
+                frame_dump.copy("final", &memory_allocator, images[image_index as usize].clone(), &mut builder);
+                // transient, and multisampled with MSAA, so there is nothing to copy it from
+                println!("Not dumping depth, the depth buffer is a transient attachment");
+            }
+
+            let command_buffer = builder.build().unwrap();
+
+            let previous_future = match fences[previous_fence_index].clone() {
+                None => {
+                    let mut now = sync::now(device.clone());
+                    now.cleanup_finished();
+                    now.boxed()
+                }
+                Some(fence) => fence.boxed(),
+            };
+
+            // vulkano drops the suboptimal flag of the present itself; the next acquire
+            // reports the same state, and is handled above
+            let rendered = previous_future
+                .join(acquire_future)
+                .then_execute(queue.clone(), command_buffer)
+                .unwrap();
+            // a present on another queue waits for the rendering through a semaphore
+            let rendered = if separate_present {
+                rendered.then_signal_semaphore().boxed()
+            } else {
+                rendered.boxed()
+            };
+            let future = rendered
+                .then_swapchain_present(
+                    present_queue.clone(),
+                    SwapchainPresentInfo::swapchain_image_index(swapchain.clone(), image_index),
+                )
+                .boxed()
+                .then_signal_fence_and_flush();
+
+            fences[image_index as usize] = match future {
+                Ok(future) => Some(Arc::new(future)),
+                Err(FlushError::OutOfDate) => {
+                    debug::log(format_args!("swapchain out of date on present, recreating next frame"));
+                    recreate_swapchain = true;
+                    None
+                }
+                Err(FlushError::DeviceLost) => gpu_hang("submitting a frame", config.fence_timeout),
+                Err(e) => {
+                    println!("Failed to flush future: {:?}", e);
+                    None
+                }
+            };
+            previous_fence_index = image_index as usize;
+
+            // recording waits for every frame, the readback buffer is reused
+            if let (Some(recorder), Some(fence)) = (&mut recorder, &fences[image_index as usize]) {
+                wait_for_frame(fence, config.fence_timeout);
+
+                if let Err(e) = recorder.save() {
+                    eprintln!("Failed to save frame, stopping: {}", e);
+                    *control_flow = ControlFlow::Exit;
+                } else if recorder.is_done() {
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+
+            if let (true, Some(fence)) = (capturing, &fences[image_index as usize]) {
+                wait_for_frame(fence, config.fence_timeout);
+                clipboard_capture.finish();
+            }
+
+            if let (Some(frame_dump), Some(fence)) = (frame_dump, &fences[image_index as usize]) {
+                wait_for_frame(fence, config.fence_timeout);
+                match frame_dump.save() {
+                    Ok(dir) => println!("Dumped the frame's targets to {}", dir.display()),
+                    Err(e) => eprintln!("Failed to dump the frame: {}", e),
+                }
+            }
+
+            frame_limiter.wait();
         },
+        Event::LoopDestroyed => {
+            // Let the GPU finish everything it was given before anything it may still be
+            // reading is destroyed: frames first, then the framebuffers over the swapchain
+            // images. The rest goes when the closure is dropped.
+            for fence in fences.iter().flatten() {
+                wait_for_frame(fence, config.fence_timeout);
+            }
+            fences.clear();
+            targets.framebuffers.clear();
+            targets.post_framebuffers.clear();
+            targets.swapchain_views.clear();
+
+            println!("Resource cache: {:?}", cache.stats());
+            if let Some(recorder) = &recorder {
+                println!("Recorded {} frames to {}", recorder.frames_written(), recorder.dir().display());
+            }
+            run_marker.remove();
+        }
         _ => {}
     });
+}
+
+// The swapchain extent for a window of `window_size`. Where the surface dictates its
+// size that wins, otherwise (`current_extent` of u32::MAX) the window size is used.
+// Either is clamped to the extents the surface allows, some compositors report a
+// range that the window can momentarily be outside of.
+fn swapchain_extent(caps: &SurfaceCapabilities, window_size: [u32; 2]) -> [u32; 2] {
+    let requested = caps.current_extent.unwrap_or(window_size);
+    let extent = [
+        requested[0].clamp(caps.min_image_extent[0], caps.max_image_extent[0]),
+        requested[1].clamp(caps.min_image_extent[1], caps.max_image_extent[1]),
+    ];
+
+    if extent != requested {
+        println!(
+            "Swapchain extent {:?} is outside the surface's {:?} to {:?}, using {:?}",
+            requested, caps.min_image_extent, caps.max_image_extent, extent,
+        );
+    }
+
+    extent
+}
+
+// Blocks until a submitted frame has finished on the GPU, but never for longer than
+// `timeout`, so a hung driver turns into a diagnostic instead of a frozen window.
+fn wait_for_frame(fence: &FenceSignalFuture<Box<dyn GpuFuture>>, timeout: Duration) {
+    match fence.wait(Some(timeout)) {
+        Ok(()) => {}
+        Err(FlushError::Timeout) | Err(FlushError::DeviceLost) => gpu_hang("waiting for a frame fence", timeout),
+        Err(e) => panic!("Failed to wait for frame fence: {:?}", e),
+    }
+}
+
+// Dropping an in-flight `FenceSignalFuture` waits on its fence without any timeout,
+// so unwinding after a hang would just freeze again. Leave the process directly.
+fn gpu_hang(operation: &str, timeout: Duration) -> ! {
+    eprintln!(
+        "The GPU made no progress while {} (timeout {:?}); the device is hung or lost. Exiting.",
+        operation, timeout,
+    );
+    std::process::exit(1)
+}
+
+// Cuts `full` into `count` side-by-side columns, each with a scissor matching its
+// rectangle so nothing drawn for one view bleeds into its neighbour.
+fn split_viewports(full: &Viewport, count: u32) -> Vec<(Viewport, Scissor)> {
+    let width = full.dimensions[0] / count as f32;
+
+    (0..count).map(|i| {
+        let viewport = Viewport {
+            origin: [full.origin[0] + width * i as f32, full.origin[1]],
+            dimensions: [width, full.dimensions[1]],
+            depth_range: full.depth_range.clone(),
+        };
+        let scissor = Scissor {
+            origin: [viewport.origin[0] as u32, viewport.origin[1] as u32],
+            dimensions: [viewport.dimensions[0] as u32, viewport.dimensions[1] as u32],
+        };
+
+        (viewport, scissor)
+    }).collect()
+}
+
+// The variants of the mesh pipeline. After a depth prepass the colour passes test
+// `Equal` against its depth and write none of their own, so each visible pixel is
+// shaded once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum MeshPass {
+    DepthOnly,
+    Lit { prepassed: bool, variant: MaterialVariant },
+    // additive with no depth test, or only the visible layer when prepassed
+    Overdraw { prepassed: bool },
+    // triangle edges in `Globals.line_color`, drawn after one of the others and biased
+    // towards the camera so only the edges of visible surfaces pass the depth test
+    Wireframe,
+}
+
+// With `vertex_pulling` there are no vertex bindings at all, `pulled_vs` reads the
+// vertex buffer through the address passed in its push constants.
+fn create_mesh_pipeline(
+    device: Arc<Device>,
+    render_pass: PipelineRenderPassType,
+    samples: SampleCount,
+    depth: DepthMode,
+    sample_shading: Option<f32>,
+    pass: MeshPass,
+    vertex_pulling: bool,
+) -> Arc<GraphicsPipeline> {
+    let (vs, vertex_input) = if vertex_pulling {
+        (pulled_vs::load(device.clone()).unwrap(), BuffersDefinition::new())
+    } else {
+        (vs::load(device.clone()).unwrap(), BuffersDefinition::new().vertex::<Vertex>())
+    };
+    let equal_depth = DepthStencilState {
+        depth: Some(DepthState {
+            enable_dynamic: false,
+            write_enable: StateMode::Fixed(false),
+            compare_op: StateMode::Fixed(CompareOp::Equal),
+        }),
+        ..DepthStencilState::disabled()
+    };
+
+    // colour and velocity
+    let (depth_stencil_state, color_blend_state, sample_shading) = match pass {
+        MeshPass::DepthOnly => {
+            let mut color_blend_state = ColorBlendState::new(2);
+            for attachment in &mut color_blend_state.attachments {
+                attachment.color_write_mask = ColorComponents::empty();
+            }
+            (depth.test(true), color_blend_state, None)
+        }
+        MeshPass::Lit { prepassed: false, .. } => (depth.test(true), ColorBlendState::new(2), sample_shading),
+        MeshPass::Lit { prepassed: true, .. } => (equal_depth, ColorBlendState::new(2), sample_shading),
+        MeshPass::Overdraw { prepassed: false } => (DepthStencilState::disabled(), ColorBlendState::new(2).blend_additive(), sample_shading),
+        MeshPass::Overdraw { prepassed: true } => (equal_depth, ColorBlendState::new(2).blend_additive(), sample_shading),
+        MeshPass::Wireframe => {
+            // the surface underneath keeps its motion vectors
+            let mut color_blend_state = ColorBlendState::new(2);
+            color_blend_state.attachments[1].color_write_mask = ColorComponents::empty();
+            (depth.overlay(), color_blend_state, None)
+        }
+    };
+
+    let rasterization_state = match pass {
+        MeshPass::Wireframe => RasterizationState {
+            polygon_mode: PolygonMode::Line,
+            // set per frame, within `line_width_range`
+            line_width: StateMode::Dynamic,
+            depth_bias: Some(DepthBiasState {
+                enable_dynamic: false,
+                bias: StateMode::Fixed(DepthBias {
+                    constant_factor: depth.toward_camera(1.0),
+                    clamp: 0.0,
+                    slope_factor: depth.toward_camera(1.0),
+                }),
+            }),
+            ..RasterizationState::new()
+        },
+        _ => RasterizationState::new(),
+    };
+
+    let builder = GraphicsPipeline::start()
+        .vertex_input_state(vertex_input)
+        .vertex_shader(vs.entry_point("main").unwrap(), ())
+        .input_assembly_state(InputAssemblyState::new())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_dynamic(1))
+        .rasterization_state(rasterization_state)
+        .depth_stencil_state(depth_stencil_state)
+        .color_blend_state(color_blend_state)
+        .multisample_state(MultisampleState {
+            rasterization_samples: samples,
+            sample_shading,
+            ..Default::default()
+        })
+        .render_pass(render_pass);
+
+    // only the mesh shader has specialization constants, so the fragment shader goes
+    // in last where each can have its own type of them; the overdraw view doesn't
+    // light anything, any variant does
+    let pipeline = match pass {
+        MeshPass::DepthOnly => {
+            let fs = depth_fs::load(device.clone()).unwrap();
+            builder.fragment_shader(fs.entry_point("main").unwrap(), ()).build(device)
+        }
+        MeshPass::Wireframe => {
+            let fs = line_fs::load(device.clone()).unwrap();
+            builder.fragment_shader(fs.entry_point("main").unwrap(), ()).build(device)
+        }
+        MeshPass::Lit { variant, .. } => {
+            let fs = fs::load(device.clone()).unwrap();
+            builder.fragment_shader(fs.entry_point("main").unwrap(), variant.specialization_constants()).build(device)
+        }
+        MeshPass::Overdraw { .. } => {
+            let fs = fs::load(device.clone()).unwrap();
+            builder
+                .fragment_shader(fs.entry_point("main").unwrap(), MaterialVariant::default().specialization_constants())
+                .build(device)
+        }
+    };
 
-    previous_frame_end
-        .as_mut()
-        .take()
-        .unwrap()
-        .cleanup_finished();
+    pipeline.unwrap()
 }
 
-fn window_size_dependent_setup(images: &[Arc<SwapchainImage>], render_pass: Arc<RenderPass>, viewport: &mut Viewport) -> Vec<Arc<Framebuffer>> {
+// Binds `pipeline` with `globals` as set 0 and draws every mesh in `draw_list`, each
+// with its world matrix for this frame and the last.
+fn draw_meshes<L, A>(
+    builder: &mut AutoCommandBufferBuilder<L, A>,
+    descriptor_set_allocator: &StandardDescriptorSetAllocator,
+    pipeline: &Arc<GraphicsPipeline>,
+    globals: Arc<dyn BufferAccess>,
+    draw_list: &[(Matrix4<f32>, Matrix4<f32>, &Mesh)],
+    vertex_pulling: bool,
+) where
+    A: CommandBufferAllocator,
+{
+    // built per pipeline, the depth-only one only sees globals from the vertex stage
+    let set = PersistentDescriptorSet::new(
+        descriptor_set_allocator,
+        pipeline.layout().set_layouts().get(0).unwrap().clone(),
+        [WriteDescriptorSet::buffer(0, globals)],
+    ).unwrap();
+
+    builder
+        .bind_pipeline_graphics(pipeline.clone())
+        .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 0, set);
+
+    for (world, previous_world, mesh) in draw_list {
+        if vertex_pulling {
+            // the command buffer doesn't see this read, the scene keeps the buffer alive
+            let address = mesh.vertex_buffer.raw_device_address().unwrap().get();
+            builder.push_constants(pipeline.layout().clone(), 0, pulled_vs::ty::PushConstants {
+                model: (*world).into(),
+                prev_model: (*previous_world).into(),
+                vertices: [address as u32, (address >> 32) as u32],
+            });
+        } else {
+            builder
+                .push_constants(pipeline.layout().clone(), 0, vs::ty::PushConstants {
+                    model: (*world).into(),
+                    prev_model: (*previous_world).into(),
+                })
+                .bind_vertex_buffers(0, mesh.vertex_buffer.clone());
+        }
+
+        builder
+            .bind_index_buffer(mesh.index_buffer.clone())
+            .draw_indexed(mesh.index_count, 1, 0, 0, 0)
+            .unwrap();
+    }
+}
+
+// Subpass 0 draws the scene into an HDR colour attachment and its motion vectors into
+// a velocity one (see `velocity`), subpass 1 tonemaps the colour into the swapchain
+// image (see `tonemap`). With MSAA the scene is drawn multisampled and resolved into
+// the single-sampled attachments at the end of subpass 0, so tonemapping still reads
+// one sample per pixel. Between consecutive subpasses the macro adds a
+// by-region dependency, which is what makes the `subpassLoad` in subpass 1 safe.
+fn create_render_pass(device: Arc<Device>, color_format: Format, depth_format: Format, samples: SampleCount) -> Arc<RenderPass> {
+    if samples == SampleCount::Sample1 {
+        vulkano::ordered_passes_renderpass!(device,
+            attachments: {
+                hdr: {
+                    load: Clear,
+                    store: DontCare,
+                    format: tonemap::HDR_FORMAT,
+                    samples: 1,
+                },
+                velocity: {
+                    load: Clear,
+                    store: Store,
+                    format: velocity::FORMAT,
+                    samples: 1,
+                },
+                color: {
+                    load: DontCare,
+                    store: Store,
+                    format: color_format,
+                    samples: 1,
+                },
+                depth: {
+                    load: Clear,
+                    store: DontCare,
+                    format: depth_format,
+                    samples: 1,
+                }
+            },
+            passes: [
+                {
+                    color: [hdr, velocity],
+                    depth_stencil: {depth},
+                    input: []
+                },
+                {
+                    color: [color],
+                    depth_stencil: {},
+                    input: [hdr]
+                }
+            ]
+        ).unwrap()
+    } else {
+        vulkano::ordered_passes_renderpass!(device,
+            attachments: {
+                msaa_hdr: {
+                    load: Clear,
+                    store: DontCare,
+                    format: tonemap::HDR_FORMAT,
+                    samples: samples as u32,
+                },
+                msaa_velocity: {
+                    load: Clear,
+                    store: DontCare,
+                    format: velocity::FORMAT,
+                    samples: samples as u32,
+                },
+                hdr: {
+                    load: DontCare,
+                    store: DontCare,
+                    format: tonemap::HDR_FORMAT,
+                    samples: 1,
+                },
+                velocity: {
+                    load: DontCare,
+                    store: Store,
+                    format: velocity::FORMAT,
+                    samples: 1,
+                },
+                color: {
+                    load: DontCare,
+                    store: Store,
+                    format: color_format,
+                    samples: 1,
+                },
+                depth: {
+                    load: Clear,
+                    store: DontCare,
+                    format: depth_format,
+                    samples: samples as u32,
+                }
+            },
+            passes: [
+                {
+                    color: [msaa_hdr, msaa_velocity],
+                    depth_stencil: {depth},
+                    input: [],
+                    resolve: [hdr, velocity]
+                },
+                {
+                    color: [color],
+                    depth_stencil: {},
+                    input: [hdr]
+                }
+            ]
+        ).unwrap()
+    }
+}
+
+// For the full-screen passes after tonemapping: one colour attachment that every
+// pixel gets written to, an intermediate image or the swapchain image.
+fn create_post_render_pass(device: Arc<Device>, format: Format) -> Arc<RenderPass> {
+    vulkano::single_pass_renderpass!(device,
+        attachments: {
+            color: {
+                load: DontCare,
+                store: Store,
+                format: format,
+                samples: 1,
+            }
+        },
+        pass: {
+            color: [color],
+            depth_stencil: {}
+        }
+    ).unwrap()
+}
+
+// Starts one of the passes after tonemapping, in `framebuffer` or with dynamic
+// rendering (no framebuffers) straight on `output`.
+fn begin_post_pass<L, A>(
+    builder: &mut AutoCommandBufferBuilder<L, A>,
+    framebuffer: Option<&Arc<Framebuffer>>,
+    output: Arc<dyn ImageViewAbstract>,
+) where
+    A: CommandBufferAllocator,
+{
+    match framebuffer {
+        Some(framebuffer) => {
+            builder
+                .begin_render_pass(
+                    RenderPassBeginInfo {
+                        clear_values: vec![None],
+                        ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
+                    },
+                    SubpassContents::Inline,
+                )
+                .unwrap();
+        }
+        None => {
+            builder.begin_rendering(color_rendering_info(output)).unwrap();
+        }
+    }
+}
+
+const CLEAR_COLOR: [f32; 4] = [0.1, 0.1, 0.1, 1.0];
+const WIREFRAME_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+// What a pipeline gets built against: subpass `subpass` of the render pass, or with
+// dynamic rendering just the formats of the attachments it will draw into.
+fn pipeline_target(render_pass: &Option<Arc<RenderPass>>, subpass: u32, color_formats: &[Format], depth_format: Option<Format>) -> PipelineRenderPassType {
+    match render_pass {
+        Some(render_pass) => Subpass::from(render_pass.clone(), subpass).unwrap().into(),
+        None => PipelineRenderingCreateInfo {
+            color_attachment_formats: color_formats.iter().copied().map(Some).collect(),
+            depth_attachment_format: depth_format,
+            ..Default::default()
+        }.into(),
+    }
+}
+
+// Matches the attachment order of `create_render_pass`.
+fn clear_values(samples: SampleCount, depth: DepthMode) -> Vec<Option<ClearValue>> {
+    let color = Some(CLEAR_COLOR.into());
+    let velocity = Some(velocity::CLEAR_VALUE.into());
+    let depth = Some(depth.clear_value().into());
+
+    if samples == SampleCount::Sample1 {
+        vec![color, velocity, None, depth]
+    } else {
+        vec![color, velocity, None, None, None, depth]
+    }
+}
+
+// Dynamic rendering equivalent of subpass 0. Unlike there the HDR target has to be
+// stored, the tonemap scope samples it afterwards.
+fn scene_rendering_info(targets: &RenderTargets, depth: DepthMode) -> RenderingInfo {
+    // drawn into directly, or multisampled and resolved into it
+    let attachment = |target: &Arc<ImageView<AttachmentImage>>, msaa: &Option<Arc<ImageView<AttachmentImage>>>, clear_value: ClearValue| {
+        match msaa {
+            Some(msaa) => RenderingAttachmentInfo {
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::DontCare,
+                clear_value: Some(clear_value),
+                resolve_info: Some(RenderingAttachmentResolveInfo {
+                    mode: ResolveMode::Average,
+                    ..RenderingAttachmentResolveInfo::image_view(target.clone())
+                }),
+                ..RenderingAttachmentInfo::image_view(msaa.clone())
+            },
+            None => RenderingAttachmentInfo {
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_value: Some(clear_value),
+                ..RenderingAttachmentInfo::image_view(target.clone())
+            },
+        }
+    };
+
+    RenderingInfo {
+        render_area_extent: targets.hdr.image().dimensions().width_height(),
+        layer_count: 1,
+        color_attachments: vec![
+            Some(attachment(&targets.hdr, &targets.msaa_hdr, CLEAR_COLOR.into())),
+            Some(attachment(&targets.velocity, &targets.msaa_velocity, velocity::CLEAR_VALUE.into())),
+        ],
+        depth_attachment: Some(RenderingAttachmentInfo {
+            load_op: LoadOp::Clear,
+            store_op: StoreOp::DontCare,
+            clear_value: Some(depth.clear_value().into()),
+            ..RenderingAttachmentInfo::image_view(targets.depth.clone())
+        }),
+        contents: SubpassContents::Inline,
+        ..Default::default()
+    }
+}
+
+// Dynamic rendering equivalent of subpass 1, or of a post pass: a full-screen draw
+// that overwrites every pixel of `view`.
+fn color_rendering_info(view: Arc<dyn ImageViewAbstract>) -> RenderingInfo {
+    RenderingInfo {
+        render_area_extent: view.image().dimensions().width_height(),
+        layer_count: 1,
+        color_attachments: vec![Some(RenderingAttachmentInfo {
+            load_op: LoadOp::DontCare,
+            store_op: StoreOp::Store,
+            ..RenderingAttachmentInfo::image_view(view)
+        })],
+        contents: SubpassContents::Inline,
+        ..Default::default()
+    }
+}
+
+// Highest count up to `requested` that colour and depth attachments both support.
+fn choose_sample_count(physical_device: &PhysicalDevice, requested: u32) -> SampleCount {
+    let color = physical_device.properties().framebuffer_color_sample_counts;
+    let depth = physical_device.properties().framebuffer_depth_sample_counts;
+
+    [
+        (SampleCount::Sample8, color.sample8 && depth.sample8),
+        (SampleCount::Sample4, color.sample4 && depth.sample4),
+        (SampleCount::Sample2, color.sample2 && depth.sample2),
+    ]
+        .into_iter()
+        .find(|&(count, supported)| supported && count as u32 <= requested)
+        .map_or(SampleCount::Sample1, |(count, _)| count)
+}
+
+// What `window_size_dependent_setup` builds against, fixed for the whole run.
+struct TargetSettings {
+    // `None` with dynamic rendering
+    render_pass: Option<Arc<RenderPass>>,
+    post_render_pass: Option<Arc<RenderPass>>,
+    // every image it names besides the scene's and the swapchain's is allocated here
+    render_graph: RenderGraph,
+    samples: SampleCount,
+    depth_format: Format,
+}
+
+// Everything that has to match the swapchain extent. Without a render pass (dynamic
+// rendering) there are no framebuffers, the views go to `begin_rendering` instead.
+struct RenderTargets {
+    framebuffers: Vec<Arc<Framebuffer>>,
+    // per pass after tonemapping in the render graph's order, then per swapchain image
+    post_framebuffers: Vec<Vec<Arc<Framebuffer>>>,
+    swapchain_views: Vec<Arc<ImageView<SwapchainImage>>>,
+    // the tonemap pass reads this, as an input attachment or a sampled image
+    hdr: Arc<ImageView<AttachmentImage>>,
+    msaa_hdr: Option<Arc<ImageView<AttachmentImage>>>,
+    // stored for the passes that read motion vectors
+    velocity: Arc<ImageView<AttachmentImage>>,
+    msaa_velocity: Option<Arc<ImageView<AttachmentImage>>>,
+    depth: Arc<ImageView<AttachmentImage>>,
+    // every image the render graph names but the swapchain's, in the order they're
+    // written: the scene's two, then the ones after it in the swapchain format
+    named: Vec<(String, Arc<ImageView<AttachmentImage>>)>,
+}
+
+impl RenderTargets {
+    // The image the render graph calls `name`, for swapchain image `image_index`.
+    fn image(&self, name: &str, image_index: usize) -> Arc<dyn ImageViewAbstract> {
+        named_image(&self.named, &self.swapchain_views[image_index], name)
+    }
+}
+
+fn named_image(
+    named: &[(String, Arc<ImageView<AttachmentImage>>)],
+    swapchain_view: &Arc<ImageView<SwapchainImage>>,
+    name: &str,
+) -> Arc<dyn ImageViewAbstract> {
+    if name == SWAPCHAIN {
+        return swapchain_view.clone();
+    }
+
+    named
+        .iter()
+        .find(|(image_name, _)| image_name == name)
+        .map(|(_, image)| image.clone())
+        .unwrap_or_else(|| panic!("The render graph has no image called `{}`", name))
+}
+
+fn window_size_dependent_setup(images: &[Arc<SwapchainImage>], settings: &TargetSettings, memory_allocator: &StandardMemoryAllocator, viewport: &mut Viewport) -> RenderTargets {
+    let samples = settings.samples;
     let dimensions = images[0].dimensions().width_height();
     viewport.dimensions = [dimensions[0] as f32, dimensions[1] as f32];
 
-    images.iter().map(|image| {
-        let view = ImageView::new_default(image.clone()).unwrap();
-        Framebuffer::new(render_pass.clone(), FramebufferCreateInfo{
-            attachments: vec![view],
-            ..Default::default()
-        },).unwrap()
-    }).collect::<Vec<_>>()
+    let depth = ImageView::new_default(
+        AttachmentImage::transient_multisampled(memory_allocator, dimensions, samples, settings.depth_format).unwrap(),
+    ).unwrap();
+    debug::set_name(&**depth.image().inner().image, "depth buffer");
+
+    // sampled by a later pass, and copied out by a frame dump (F9)
+    let dumpable = ImageUsage {
+        sampled: true,
+        transfer_src: true,
+        ..ImageUsage::empty()
+    };
+
+    // only the render pass can keep it on-tile, dynamic rendering samples it later
+    let hdr = ImageView::new_default(match settings.render_pass {
+        Some(_) => AttachmentImage::transient_input_attachment(memory_allocator, dimensions, tonemap::HDR_FORMAT).unwrap(),
+        None => AttachmentImage::with_usage(memory_allocator, dimensions, tonemap::HDR_FORMAT, dumpable).unwrap(),
+    }).unwrap();
+    debug::set_name(&**hdr.image().inner().image, "hdr colour");
+
+    // one multisampled target is enough, it resolves into the single HDR attachment
+    let msaa_hdr = (samples != SampleCount::Sample1).then(|| {
+        let image = AttachmentImage::transient_multisampled(
+            memory_allocator,
+            dimensions,
+            samples,
+            tonemap::HDR_FORMAT,
+        ).unwrap();
+        debug::set_name(&**image.inner().image, "msaa hdr colour");
+        ImageView::new_default(image).unwrap()
+    });
+
+    let velocity = ImageView::new_default(
+        AttachmentImage::with_usage(memory_allocator, dimensions, velocity::FORMAT, dumpable).unwrap(),
+    ).unwrap();
+    debug::set_name(&**velocity.image().inner().image, "velocity");
+
+    let msaa_velocity = (samples != SampleCount::Sample1).then(|| {
+        let image = AttachmentImage::transient_multisampled(
+            memory_allocator,
+            dimensions,
+            samples,
+            velocity::FORMAT,
+        ).unwrap();
+        debug::set_name(&**image.inner().image, "msaa velocity");
+        ImageView::new_default(image).unwrap()
+    });
+
+    let graph = &settings.render_graph;
+    let mut named = vec![
+        (graph.scene().outputs[0].clone(), hdr.clone()),
+        (graph.scene().outputs[1].clone(), velocity.clone()),
+    ];
+    for name in graph.intermediates() {
+        let image = AttachmentImage::with_usage(memory_allocator, dimensions, images[0].format(), dumpable).unwrap();
+        debug::set_name(&**image.inner().image, name);
+        named.push((name.to_owned(), ImageView::new_default(image).unwrap()));
+    }
+
+    let swapchain_views = images.iter().enumerate().map(|(i, image)| {
+        debug::set_name(&**image.inner().image, &format!("swapchain image {}", i));
+        ImageView::new_default(image.clone()).unwrap()
+    }).collect::<Vec<_>>();
+
+    let framebuffers = match &settings.render_pass {
+        Some(render_pass) => swapchain_views.iter().map(|view| {
+            let output = named_image(&named, view, &graph.tonemap().outputs[0]);
+            let attachments: Vec<Arc<dyn ImageViewAbstract>> = match (&msaa_hdr, &msaa_velocity) {
+                (Some(msaa_hdr), Some(msaa_velocity)) => vec![msaa_hdr.clone(), msaa_velocity.clone(), hdr.clone(), velocity.clone(), output, depth.clone()],
+                _ => vec![hdr.clone(), velocity.clone(), output, depth.clone()],
+            };
+
+            Framebuffer::new(render_pass.clone(), FramebufferCreateInfo{
+                attachments,
+                ..Default::default()
+            },).unwrap()
+        }).collect::<Vec<_>>(),
+        None => Vec::new(),
+    };
+
+    // one per swapchain image, even where they all draw into the same intermediate
+    let post_framebuffers = match &settings.post_render_pass {
+        Some(render_pass) => graph.post_passes().iter().map(|pass| {
+            swapchain_views.iter().map(|view| {
+                Framebuffer::new(render_pass.clone(), FramebufferCreateInfo{
+                    attachments: vec![named_image(&named, view, &pass.outputs[0])],
+                    ..Default::default()
+                },).unwrap()
+            }).collect::<Vec<_>>()
+        }).collect::<Vec<_>>(),
+        None => Vec::new(),
+    };
+
+    RenderTargets {
+        framebuffers,
+        post_framebuffers,
+        swapchain_views,
+        hdr,
+        msaa_hdr,
+        velocity,
+        msaa_velocity,
+        depth,
+        named,
+    }
+}
+
+// A turntable with a two-segment arm on top. Joints are mesh-less nodes so their
+// rotation is inherited by everything further down while the boxes below them
+// carry the per-segment scale.
+fn articulated_arm(cube: &Mesh) -> Node {
+    let segment = |offset: f32, width: f32, length: f32| {
+        Node::new(
+            Matrix4::from_translation(Vector3::new(0.0, offset, 0.0))
+                * Matrix4::from_nonuniform_scale(width, length, width),
+        ).with_mesh(cube.clone())
+    };
+
+    let elbow = Node::new(Matrix4::identity())
+        .with_child(segment(0.5, 0.2, 1.0));
+
+    let shoulder = Node::new(Matrix4::identity())
+        .with_child(segment(0.75, 0.3, 1.5))
+        .with_child(elbow);
+
+    Node::new(Matrix4::identity())
+        .with_child(segment(0.1, 2.0, 0.2))
+        .with_child(shoulder)
+}
+
+fn animate_arm(scene: &mut Node, time: f32) {
+    scene.local_transform = Matrix4::from_angle_y(Rad(time * 0.5));
+
+    if let Some(shoulder) = scene.node_mut(&[1]) {
+        shoulder.local_transform = Matrix4::from_translation(Vector3::new(0.0, 0.2, 0.0))
+            * Matrix4::from_angle_z(Rad(time.sin() * 0.6));
+    }
+
+    if let Some(elbow) = scene.node_mut(&[1, 1]) {
+        elbow.local_transform = Matrix4::from_translation(Vector3::new(0.0, 1.5, 0.0))
+            * Matrix4::from_angle_z(Rad((time * 1.7).sin() * 0.9));
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+            #version 450
+
+            layout(location = 0) in vec3 position;
+            layout(location = 1) in vec3 normal;
+            layout(location = 2) in vec2 uv;
+
+            layout(location = 0) out vec3 v_normal;
+            layout(location = 1) out vec3 v_world;
+            layout(location = 2) out vec2 v_uv;
+            layout(location = 3) out vec4 v_clip;
+            layout(location = 4) out vec4 v_prev_clip;
 
+            layout(set = 0, binding = 0) uniform Globals {
+                mat4 view;
+                mat4 proj;
+                // last frame's proj * view, for motion vectors
+                mat4 prev_view_proj;
+                vec4 line_color;
+                // the view's viewport over the whole target, for motion vectors
+                vec2 viewport_scale;
+                int debug_view;
+            } globals;
+
+            layout(push_constant) uniform PushConstants {
+                mat4 model;
+                // last frame's, for motion vectors
+                mat4 prev_model;
+            } push;
+
+            // the depth prepass and the colour pass must agree on depth exactly
+            invariant gl_Position;
+
+            void main() {
+                vec4 world = push.model * vec4(position, 1.0);
+                v_normal = transpose(inverse(mat3(push.model))) * normal;
+                v_world = world.xyz;
+                v_uv = uv;
+                gl_Position = globals.proj * globals.view * world;
+                v_clip = gl_Position;
+                v_prev_clip = globals.prev_view_proj * push.prev_model * vec4(position, 1.0);
+            }
+        ",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
+    }
+}
+
+// Same outputs as `vs`, but the vertex is fetched from `push.vertices` instead of
+// vertex input. The layout has to match `scene::Vertex`: 8 floats, tightly packed.
+mod pulled_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        vulkan_version: "1.1",
+        spirv_version: "1.3",
+        src: "
+            #version 450
+            #extension GL_EXT_buffer_reference : require
+            #extension GL_EXT_buffer_reference_uvec2 : require
+
+            layout(buffer_reference, std430, buffer_reference_align = 4) readonly buffer Vertices {
+                float data[];
+            };
+
+            layout(location = 0) out vec3 v_normal;
+            layout(location = 1) out vec3 v_world;
+            layout(location = 2) out vec2 v_uv;
+            layout(location = 3) out vec4 v_clip;
+            layout(location = 4) out vec4 v_prev_clip;
+
+            layout(set = 0, binding = 0) uniform Globals {
+                mat4 view;
+                mat4 proj;
+                // last frame's proj * view, for motion vectors
+                mat4 prev_view_proj;
+                vec4 line_color;
+                // the view's viewport over the whole target, for motion vectors
+                vec2 viewport_scale;
+                int debug_view;
+            } globals;
+
+            layout(push_constant) uniform PushConstants {
+                mat4 model;
+                mat4 prev_model;
+                // device address of the vertex buffer, low word first
+                uvec2 vertices;
+            } push;
+
+            const int VERTEX_FLOATS = 8;
+
+            invariant gl_Position;
+
+            void main() {
+                Vertices vertices = Vertices(push.vertices);
+                int base = gl_VertexIndex * VERTEX_FLOATS;
+
+                vec3 position = vec3(vertices.data[base], vertices.data[base + 1], vertices.data[base + 2]);
+                vec3 normal = vec3(vertices.data[base + 3], vertices.data[base + 4], vertices.data[base + 5]);
+                vec2 uv = vec2(vertices.data[base + 6], vertices.data[base + 7]);
+
+                vec4 world = push.model * vec4(position, 1.0);
+                v_normal = transpose(inverse(mat3(push.model))) * normal;
+                v_world = world.xyz;
+                v_uv = uv;
+                gl_Position = globals.proj * globals.view * world;
+                v_clip = gl_Position;
+                v_prev_clip = globals.prev_view_proj * push.prev_model * vec4(position, 1.0);
+            }
+        ",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
+    }
+}
+
+// Depth prepass: no colour output, the depth comes from the rasterizer.
+mod depth_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 450
+
+            void main() {}
+        "
+    }
+}
+
+// Wireframe overlay: every edge in a single colour.
+mod line_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 450
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 0) uniform Globals {
+                mat4 view;
+                mat4 proj;
+                mat4 prev_view_proj;
+                vec4 line_color;
+            } globals;
+
+            void main() {
+                f_color = globals.line_color;
+            }
+        "
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 450
+
+            layout(location = 0) in vec3 v_normal;
+            layout(location = 1) in vec3 v_world;
+            layout(location = 2) in vec2 v_uv;
+            layout(location = 3) in vec4 v_clip;
+            layout(location = 4) in vec4 v_prev_clip;
+
+            layout(location = 0) out vec4 f_color;
+            // see `velocity`
+            layout(location = 1) out vec2 f_velocity;
+
+            layout(set = 0, binding = 0) uniform Globals {
+                mat4 view;
+                mat4 proj;
+                mat4 prev_view_proj;
+                vec4 line_color;
+                vec2 viewport_scale;
+                // see `DebugView`
+                int debug_view;
+            } globals;
+
+            // see `MaterialVariant`
+            layout(constant_id = 0) const bool SPECULAR = true;
+            layout(constant_id = 1) const bool FLAT_SHADING = false;
+
+            const vec3 LIGHT_DIRECTION = normalize(vec3(0.4, 1.0, 0.6));
+            const vec3 ALBEDO = vec3(0.8, 0.5, 0.2);
+
+            // high enough to give the thin, aliasing-prone highlights sample shading is for
+            const float SHININESS = 256.0;
+
+            // inverts the depth mapping of `camera::perspective`, either way round
+            float linear_depth(float depth) {
+                return globals.proj[3][2] / (depth + globals.proj[2][2]);
+            }
+
+            void main() {
+                // dFdx runs right and dFdy down the screen, so dFdy x dFdx faces the camera
+                vec3 n = FLAT_SHADING
+                    ? normalize(cross(dFdy(v_world), dFdx(v_world)))
+                    : normalize(v_normal);
+                // NDC spans two UV units of the viewport, and both have Y pointing
+                // down; scaled to UV of the whole target, which is what gets sampled
+                f_velocity = (v_clip.xy / v_clip.w - v_prev_clip.xy / v_prev_clip.w) * 0.5 * globals.viewport_scale;
+
+                switch (globals.debug_view) {
+                case 1:
+                    f_color = vec4(n * 0.5 + 0.5, 1.0);
+                    return;
+                case 2:
+                    // the far plane is at 1.0, or at 0.0 with reversed-Z
+                    f_color = vec4(vec3(linear_depth(gl_FragCoord.z) / max(linear_depth(0.0), linear_depth(1.0))), 1.0);
+                    return;
+                case 3:
+                    f_color = vec4(v_uv, 0.0, 1.0);
+                    return;
+                case 4:
+                    f_color = vec4(ALBEDO, 1.0);
+                    return;
+                case 5:
+                    // ten layers to reach white
+                    f_color = vec4(vec3(0.1), 1.0);
+                    return;
+                case 6:
+                    // still is grey, a tenth of the screen per frame saturates
+                    f_color = vec4(f_velocity * 5.0 + 0.5, 0.5, 1.0);
+                    return;
+                }
+
+                vec3 eye = inverse(globals.view)[3].xyz;
+                vec3 h = normalize(LIGHT_DIRECTION + normalize(eye - v_world));
+
+                float diffuse = max(dot(n, LIGHT_DIRECTION), 0.0);
+                float specular = SPECULAR ? pow(max(dot(n, h), 0.0), SHININESS) : 0.0;
+                // linear HDR, the tonemap subpass takes it from here
+                f_color = vec4(ALBEDO * (0.2 + 0.8 * diffuse) + vec3(specular), 1.0);
+            }
+        "
+    }
 }
@@ -0,0 +1,62 @@
+// Lighting features of the mesh fragment shader that are switched at pipeline
+// creation through specialization constants, so one shader covers every combination
+// and the driver compiles out what a variant doesn't use.
+
+use vulkano::pipeline::GraphicsPipeline;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::{fs, MeshPass};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialVariant {
+    // the Blinn-Phong highlight on top of the diffuse term (H toggles it)
+    pub specular: bool,
+    // one normal per triangle from screen-space derivatives of the position instead
+    // of the interpolated vertex normals (F toggles it)
+    pub flat_shading: bool,
+}
+
+impl Default for MaterialVariant {
+    fn default() -> Self {
+        MaterialVariant {
+            specular: true,
+            flat_shading: false,
+        }
+    }
+}
+
+impl MaterialVariant {
+    pub fn specialization_constants(self) -> fs::SpecializationConstants {
+        fs::SpecializationConstants {
+            specular: self.specular as u32,
+            flat_shading: self.flat_shading as u32,
+        }
+    }
+}
+
+// Mesh pipelines keyed by pass, which carries the variant for the lit one, each built
+// the first time it's asked for. Building one stalls the frame, so the ones used from
+// the start are best asked for up front.
+pub struct MaterialPipelines<B> {
+    build: B,
+    pipelines: HashMap<MeshPass, Arc<GraphicsPipeline>>,
+}
+
+impl<B> MaterialPipelines<B>
+where
+    B: FnMut(MeshPass) -> Arc<GraphicsPipeline>,
+{
+    pub fn new(build: B) -> Self {
+        MaterialPipelines {
+            build,
+            pipelines: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, pass: MeshPass) -> Arc<GraphicsPipeline> {
+        let build = &mut self.build;
+        self.pipelines.entry(pass).or_insert_with(|| build(pass)).clone()
+    }
+}
@@ -0,0 +1,58 @@
+// Motion blur averages each pixel's colour along its motion vector (see `velocity`),
+// as if the shutter had stayed open for part of the frame. Like FXAA it reads
+// neighbouring pixels, so it is a pass of its own on the tonemapped image, before
+// FXAA when both are on. Blurring after tonemapping works the same on both render
+// paths, at the cost of highlights streaking less brightly than they would in HDR.
+//
+// Only a pixel's own motion is used, a moving object smears what it covers but
+// doesn't blur out over a still background.
+
+use vulkano::device::Device;
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::render_pass::PipelineRenderPassType;
+use vulkano::pipeline::graphics::vertex_input::VertexInputState;
+use vulkano::pipeline::graphics::viewport::ViewportState;
+use vulkano::pipeline::GraphicsPipeline;
+
+use std::sync::Arc;
+
+use crate::sampler::SamplerKey;
+use crate::tonemap;
+
+// For both the colour and the velocity.
+pub const SAMPLER: SamplerKey = SamplerKey::linear_clamp();
+
+pub fn push_constants(extent: [u32; 2], strength: f32, max_samples: u32) -> fs::ty::MotionBlurPush {
+    fs::ty::MotionBlurPush {
+        inverse_size: [1.0 / extent[0] as f32, 1.0 / extent[1] as f32],
+        strength,
+        max_samples: max_samples as i32,
+    }
+}
+
+pub fn create_pipeline(device: Arc<Device>, render_pass: PipelineRenderPassType) -> Arc<GraphicsPipeline> {
+    let vs = tonemap::vs::load(device.clone()).unwrap();
+    let fs = fs::load(device.clone()).unwrap();
+
+    GraphicsPipeline::start()
+        .vertex_input_state(VertexInputState::new())
+        .vertex_shader(vs.entry_point("main").unwrap(), ())
+        .input_assembly_state(InputAssemblyState::new())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_dynamic(1))
+        .fragment_shader(fs.entry_point("main").unwrap(), ())
+        .render_pass(render_pass)
+        .build(device)
+        .unwrap()
+}
+
+pub mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/shaders/motion_blur.frag",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
+    }
+}
@@ -0,0 +1,58 @@
+use winit::event::VirtualKeyCode;
+
+use std::fmt;
+
+// The passes after the scene that can be switched off live, to compare a frame with
+// and without them. A pass that's off is skipped, and what it would have read goes on
+// to the next one; if that leaves the result outside the swapchain image, it's
+// copied there. Tonemapping also encodes the output, so switching it off only drops
+// exposure and the curve, like the debug views do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PassFlags(u32);
+
+impl PassFlags {
+    pub const TONEMAP: PassFlags = PassFlags(1 << 0);
+    pub const MOTION_BLUR: PassFlags = PassFlags(1 << 1);
+    pub const FXAA: PassFlags = PassFlags(1 << 2);
+
+    // in the order they run in, for printing
+    const NAMED: [(PassFlags, &'static str); 3] = [
+        (PassFlags::TONEMAP, "tonemap"),
+        (PassFlags::MOTION_BLUR, "motion blur"),
+        (PassFlags::FXAA, "fxaa"),
+    ];
+
+    pub fn all() -> Self {
+        PassFlags(PassFlags::TONEMAP.0 | PassFlags::MOTION_BLUR.0 | PassFlags::FXAA.0)
+    }
+
+    pub fn contains(self, flags: PassFlags) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+
+    pub fn toggle(&mut self, flags: PassFlags) {
+        self.0 ^= flags.0;
+    }
+
+    // F5 to F7, in the order the passes run in.
+    pub fn from_key(key: VirtualKeyCode) -> Option<Self> {
+        match key {
+            VirtualKeyCode::F5 => Some(PassFlags::TONEMAP),
+            VirtualKeyCode::F6 => Some(PassFlags::MOTION_BLUR),
+            VirtualKeyCode::F7 => Some(PassFlags::FXAA),
+            _ => None,
+        }
+    }
+}
+
+// e.g. `tonemap on, motion blur off, fxaa on`
+impl fmt::Display for PassFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (flag, name)) in PassFlags::NAMED.iter().enumerate() {
+            let separator = if i == 0 { "" } else { ", " };
+            write!(f, "{}{} {}", separator, name, if self.contains(*flag) { "on" } else { "off" })?;
+        }
+
+        Ok(())
+    }
+}
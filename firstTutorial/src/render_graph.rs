@@ -0,0 +1,294 @@
+// The passes of a frame as data, read from RON (see `render_graph.ron`): each pass
+// names the images it reads and writes, and runs after the passes writing its
+// inputs. `swapchain` is the image being presented; every other image a pass
+// writes is allocated at the swapchain's extent by `window_size_dependent_setup`.
+//
+// The graph is checked when it's loaded: every input has to be written by some
+// pass, every image by one pass only, and the passes can't depend on each other in
+// a cycle. Barriers between the passes aren't recorded from the graph, vulkano's
+// command buffer builder inserts them itself from the images each pass uses.
+//
+// The scene and tonemap passes are the two subpasses of the main render pass, so
+// they always come first, and only the tonemap pass reads the scene's colour (with
+// a render pass it never leaves the tile). The passes after them each get a render
+// pass of their own.
+
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::mem;
+
+// The graph a run starts from unless `--render-graph` names another file.
+pub const DEFAULT: &str = include_str!("../render_graph.ron");
+
+pub const SWAPCHAIN: &str = "swapchain";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum PassKind {
+    // writes the HDR colour and the motion vectors
+    Scene,
+    // reads the HDR colour
+    Tonemap,
+    // reads the colour and the motion vectors
+    MotionBlur,
+    // reads the colour
+    Fxaa,
+}
+
+impl PassKind {
+    // How many images the pass reads and writes, inputs in the order its shader binds
+    // them.
+    fn arity(self) -> (usize, usize) {
+        match self {
+            PassKind::Scene => (0, 2),
+            PassKind::Tonemap | PassKind::Fxaa => (1, 1),
+            PassKind::MotionBlur => (2, 1),
+        }
+    }
+
+    // what the GPU profile calls it
+    pub fn label(self) -> &'static str {
+        match self {
+            PassKind::Scene => "scene",
+            PassKind::Tonemap => "tonemap",
+            PassKind::MotionBlur => "motion blur",
+            PassKind::Fxaa => "fxaa",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Pass {
+    pub name: String,
+    pub kind: PassKind,
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct GraphFile {
+    passes: Vec<Pass>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RenderGraph {
+    // in the order they run
+    passes: Vec<Pass>,
+}
+
+impl RenderGraph {
+    pub fn from_ron(source: &str) -> Result<Self, String> {
+        let file: GraphFile = ron::from_str(source).map_err(|e| e.to_string())?;
+        RenderGraph::new(file.passes)
+    }
+
+    // Checks `passes` and sorts them so each runs after the ones writing its inputs,
+    // otherwise in the order they're listed.
+    fn new(passes: Vec<Pass>) -> Result<Self, String> {
+        let mut writers = HashMap::new();
+        for (index, pass) in passes.iter().enumerate() {
+            if passes[..index].iter().any(|other| other.name == pass.name) {
+                return Err(format!("there are two passes called `{}`", pass.name));
+            }
+
+            let (inputs, outputs) = pass.kind.arity();
+            if pass.inputs.len() != inputs || pass.outputs.len() != outputs {
+                return Err(format!(
+                    "`{}` is a {:?} pass, which reads {} images and writes {}",
+                    pass.name, pass.kind, inputs, outputs,
+                ));
+            }
+
+            for output in &pass.outputs {
+                if let Some(other) = writers.insert(output.as_str(), index) {
+                    return Err(format!("`{}` is written by both `{}` and `{}`", output, passes[other].name, pass.name));
+                }
+            }
+        }
+
+        for pass in &passes {
+            for input in &pass.inputs {
+                if input == SWAPCHAIN {
+                    return Err(format!("`{}` reads the swapchain image, which is only presented", pass.name));
+                }
+                if !writers.contains_key(input.as_str()) {
+                    return Err(format!("`{}` reads `{}`, which no pass writes", pass.name, input));
+                }
+            }
+        }
+        if !writers.contains_key(SWAPCHAIN) {
+            return Err("no pass writes the swapchain image".to_owned());
+        }
+
+        // Kahn's algorithm: a pass is ready once every pass writing one of its inputs
+        // has been taken
+        let mut waiting = passes.iter().map(|pass| pass.inputs.len()).collect::<Vec<_>>();
+        let mut order = Vec::with_capacity(passes.len());
+        while let Some(next) = (0..passes.len()).find(|index| waiting[*index] == 0 && !order.contains(index)) {
+            order.push(next);
+            for (index, pass) in passes.iter().enumerate() {
+                waiting[index] -= pass.inputs.iter().filter(|input| writers[input.as_str()] == next).count();
+            }
+        }
+        if order.len() < passes.len() {
+            let stuck = passes
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| !order.contains(index))
+                .map(|(_, pass)| format!("`{}`", pass.name))
+                .collect::<Vec<_>>();
+            return Err(format!("{} can never run, their inputs depend on each other in a cycle", stuck.join(", ")));
+        }
+        let passes = order.into_iter().map(|index| passes[index].clone()).collect::<Vec<_>>();
+
+        let scene_colour = match passes.as_slice() {
+            [scene, tonemap, ..]
+                if scene.kind == PassKind::Scene
+                    && tonemap.kind == PassKind::Tonemap
+                    && tonemap.inputs[0] == scene.outputs[0] => scene.outputs[0].clone(),
+            _ => return Err("the graph has to start with a Scene pass and a Tonemap pass reading its colour".to_owned()),
+        };
+        if passes[0].outputs.iter().any(|output| output == SWAPCHAIN) {
+            return Err(format!("`{}` writes HDR colour and motion vectors, not the swapchain image", passes[0].name));
+        }
+
+        for pass in &passes[2..] {
+            if matches!(pass.kind, PassKind::Scene | PassKind::Tonemap) {
+                return Err(format!("`{}` is a second {:?} pass", pass.name, pass.kind));
+            }
+            if pass.inputs.contains(&scene_colour) {
+                return Err(format!("`{}` reads `{}`, only the tonemap pass can", pass.name, scene_colour));
+            }
+        }
+
+        // which also leaves the pass writing the swapchain image last
+        for pass in &passes[1..] {
+            let output = &pass.outputs[0];
+            if output != SWAPCHAIN && !passes.iter().any(|reader| reader.inputs.contains(output)) {
+                return Err(format!("nothing reads `{}`, written by `{}`", output, pass.name));
+            }
+        }
+
+        Ok(RenderGraph { passes })
+    }
+
+    // Drops the passes `keep` turns down. Whatever read a dropped pass's output reads
+    // its colour input instead, and without the pass writing the swapchain image the
+    // one writing that input writes it.
+    pub fn retain(&mut self, keep: impl Fn(PassKind) -> bool) -> Result<(), String> {
+        let mut passes = mem::take(&mut self.passes);
+
+        while let Some(index) = passes.iter().position(|pass| !keep(pass.kind)) {
+            let dropped = passes.remove(index);
+            let (from, to) = if dropped.outputs[0] == SWAPCHAIN {
+                (dropped.inputs[0].clone(), SWAPCHAIN.to_owned())
+            } else {
+                (dropped.outputs[0].clone(), dropped.inputs[0].clone())
+            };
+
+            for name in passes.iter_mut().flat_map(|pass| pass.inputs.iter_mut().chain(&mut pass.outputs)) {
+                if *name == from {
+                    *name = to.clone();
+                }
+            }
+        }
+
+        *self = RenderGraph::new(passes)?;
+        Ok(())
+    }
+
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    pub fn scene(&self) -> &Pass {
+        &self.passes[0]
+    }
+
+    pub fn tonemap(&self) -> &Pass {
+        &self.passes[1]
+    }
+
+    // Everything after tonemapping, in the order it runs.
+    pub fn post_passes(&self) -> &[Pass] {
+        &self.passes[2..]
+    }
+
+    pub fn contains(&self, kind: PassKind) -> bool {
+        self.passes.iter().any(|pass| pass.kind == kind)
+    }
+
+    // The images written after the scene that need allocating, which is all of them
+    // but the swapchain image.
+    pub fn intermediates(&self) -> impl Iterator<Item = &str> {
+        self.passes[1..]
+            .iter()
+            .map(|pass| pass.outputs[0].as_str())
+            .filter(|&name| name != SWAPCHAIN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(graph: &RenderGraph) -> Vec<&str> {
+        graph.passes().iter().map(|pass| pass.name.as_str()).collect()
+    }
+
+    #[test]
+    fn default_graph_is_valid() {
+        let graph = RenderGraph::from_ron(DEFAULT).unwrap();
+
+        assert_eq!(names(&graph), ["scene", "tonemap", "motion blur", "fxaa"]);
+        assert_eq!(graph.intermediates().collect::<Vec<_>>(), ["ldr", "blurred"]);
+    }
+
+    #[test]
+    fn passes_run_after_their_inputs() {
+        let graph = RenderGraph::from_ron(r#"(passes: [
+            (name: "fxaa", kind: Fxaa, inputs: ["ldr"], outputs: ["swapchain"]),
+            (name: "tonemap", kind: Tonemap, inputs: ["hdr"], outputs: ["ldr"]),
+            (name: "scene", kind: Scene, outputs: ["hdr", "velocity"]),
+        ])"#).unwrap();
+
+        assert_eq!(names(&graph), ["scene", "tonemap", "fxaa"]);
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let error = RenderGraph::from_ron(r#"(passes: [
+            (name: "scene", kind: Scene, outputs: ["hdr", "velocity"]),
+            (name: "tonemap", kind: Tonemap, inputs: ["hdr"], outputs: ["ldr"]),
+            (name: "a", kind: Fxaa, inputs: ["b"], outputs: ["a"]),
+            (name: "b", kind: Fxaa, inputs: ["a"], outputs: ["b"]),
+            (name: "fxaa", kind: Fxaa, inputs: ["ldr"], outputs: ["swapchain"]),
+        ])"#).unwrap_err();
+
+        assert!(error.contains("cycle"), "{}", error);
+    }
+
+    #[test]
+    fn missing_images_are_rejected() {
+        let error = RenderGraph::from_ron(r#"(passes: [
+            (name: "scene", kind: Scene, outputs: ["hdr", "velocity"]),
+            (name: "tonemap", kind: Tonemap, inputs: ["hdr"], outputs: ["ldr"]),
+            (name: "fxaa", kind: Fxaa, inputs: ["bloom"], outputs: ["swapchain"]),
+        ])"#).unwrap_err();
+
+        assert!(error.contains("`bloom`, which no pass writes"), "{}", error);
+    }
+
+    #[test]
+    fn dropped_passes_are_bridged() {
+        let mut graph = RenderGraph::from_ron(DEFAULT).unwrap();
+        graph.retain(|kind| kind != PassKind::MotionBlur).unwrap();
+        assert_eq!(names(&graph), ["scene", "tonemap", "fxaa"]);
+        assert_eq!(graph.post_passes()[0].inputs, ["ldr"]);
+
+        graph.retain(|kind| kind != PassKind::Fxaa).unwrap();
+        assert_eq!(names(&graph), ["scene", "tonemap"]);
+        assert_eq!(graph.tonemap().outputs, [SWAPCHAIN]);
+    }
+}
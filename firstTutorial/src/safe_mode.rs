@@ -0,0 +1,33 @@
+use std::fs;
+use std::path::PathBuf;
+
+// Written on startup and removed on a clean exit, so finding it there at the next
+// start means that run crashed, hung or was killed.
+const MARKER: &str = ".firstTutorial.running";
+
+pub struct RunMarker {
+    path: PathBuf,
+}
+
+impl RunMarker {
+    // Whether the last run left its marker behind.
+    pub fn left_behind() -> bool {
+        PathBuf::from(MARKER).exists()
+    }
+
+    // A failure to write is only reported, the next run then can't tell it crashed.
+    pub fn create() -> Self {
+        let path = PathBuf::from(MARKER);
+        if let Err(e) = fs::write(&path, std::process::id().to_string()) {
+            eprintln!("Failed to write {}: {}", path.display(), e);
+        }
+
+        RunMarker { path }
+    }
+
+    pub fn remove(&self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            eprintln!("Failed to remove {}: {}", self.path.display(), e);
+        }
+    }
+}
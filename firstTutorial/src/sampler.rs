@@ -0,0 +1,106 @@
+use vulkano::device::Device;
+use vulkano::sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode, LOD_CLAMP_NONE};
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// Everything that tells two samplers apart. Anisotropy and LOD bias are floats, so
+// the key hashes their bits; the same value written the same way hits the cache.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplerKey {
+    pub filter: Filter,
+    pub address_mode: SamplerAddressMode,
+    // max anisotropy, `None` for plain filtering
+    pub anisotropy: Option<f32>,
+    pub mipmap_mode: SamplerMipmapMode,
+    // added on top of the cache's global bias
+    pub lod_bias: f32,
+}
+
+impl SamplerKey {
+    pub const fn linear_clamp() -> Self {
+        SamplerKey {
+            filter: Filter::Linear,
+            address_mode: SamplerAddressMode::ClampToEdge,
+            anisotropy: None,
+            mipmap_mode: SamplerMipmapMode::Linear,
+            lod_bias: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Bits {
+    filter: Filter,
+    address_mode: SamplerAddressMode,
+    anisotropy: Option<u32>,
+    mipmap_mode: SamplerMipmapMode,
+    lod_bias: u32,
+}
+
+// Hands out one shared sampler per distinct `SamplerKey`. The global LOD bias is
+// folded into every sampler it creates, so after changing it callers have to ask
+// for their samplers again (descriptor sets built every frame already do).
+pub struct SamplerCache {
+    device: Arc<Device>,
+    samplers: Mutex<HashMap<Bits, Arc<Sampler>>>,
+    lod_bias: Mutex<f32>,
+}
+
+impl SamplerCache {
+    pub fn new(device: Arc<Device>) -> Self {
+        SamplerCache {
+            device,
+            samplers: Mutex::new(HashMap::new()),
+            lod_bias: Mutex::new(0.0),
+        }
+    }
+
+    pub fn get(&self, key: SamplerKey) -> Arc<Sampler> {
+        let properties = self.device.physical_device().properties();
+
+        // anisotropy needs the device feature, and both values have device limits
+        let anisotropy = key.anisotropy
+            .filter(|_| self.device.enabled_features().sampler_anisotropy)
+            .map(|a| a.clamp(1.0, properties.max_sampler_anisotropy));
+        let lod_bias = (key.lod_bias + self.lod_bias())
+            .clamp(-properties.max_sampler_lod_bias, properties.max_sampler_lod_bias);
+
+        let bits = Bits {
+            filter: key.filter,
+            address_mode: key.address_mode,
+            anisotropy: anisotropy.map(f32::to_bits),
+            mipmap_mode: key.mipmap_mode,
+            lod_bias: lod_bias.to_bits(),
+        };
+
+        self.samplers.lock().unwrap()
+            .entry(bits)
+            .or_insert_with(|| {
+                Sampler::new(self.device.clone(), SamplerCreateInfo {
+                    mag_filter: key.filter,
+                    min_filter: key.filter,
+                    mipmap_mode: key.mipmap_mode,
+                    address_mode: [key.address_mode; 3],
+                    mip_lod_bias: lod_bias,
+                    anisotropy,
+                    // the default clamps to the base level, which would make the bias useless
+                    lod: 0.0..=LOD_CLAMP_NONE,
+                    ..Default::default()
+                })
+                    .unwrap()
+            })
+            .clone()
+    }
+
+    pub fn lod_bias(&self) -> f32 {
+        *self.lod_bias.lock().unwrap()
+    }
+
+    // Negative values sharpen, positive values blur. Samplers created with the old
+    // bias stay cached so switching back is free.
+    pub fn set_lod_bias(&self, lod_bias: f32) {
+        let max = self.device.physical_device().properties().max_sampler_lod_bias;
+        *self.lod_bias.lock().unwrap() = lod_bias.clamp(-max, max);
+    }
+}
@@ -0,0 +1,153 @@
+use cgmath::{Matrix4, SquareMatrix};
+use bytemuck::{Pod, Zeroable};
+
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::device::DeviceOwned;
+use vulkano::memory::allocator::StandardMemoryAllocator;
+
+use std::sync::Arc;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+vulkano::impl_vertex!(Vertex, position, normal, uv);
+
+// GPU buffers for one piece of geometry. Cloning only clones the `Arc`s, so the
+// same mesh can hang off any number of nodes.
+#[derive(Clone)]
+pub struct Mesh {
+    pub vertex_buffer: Arc<CpuAccessibleBuffer<[Vertex]>>,
+    pub index_buffer: Arc<CpuAccessibleBuffer<[u16]>>,
+    pub index_count: u32,
+}
+
+impl Mesh {
+    pub fn new(memory_allocator: &StandardMemoryAllocator, vertices: Vec<Vertex>, indices: Vec<u16>) -> Self {
+        let index_count = indices.len() as u32;
+
+        let vertex_buffer = CpuAccessibleBuffer::from_iter(
+            memory_allocator,
+            BufferUsage {
+                vertex_buffer: true,
+                // for vertex pulling, only allowed with the feature on
+                shader_device_address: memory_allocator.device().enabled_features().buffer_device_address,
+                ..BufferUsage::empty()
+            },
+            false,
+            vertices,
+        )
+            .unwrap();
+
+        let index_buffer = CpuAccessibleBuffer::from_iter(
+            memory_allocator,
+            BufferUsage {
+                index_buffer: true,
+                ..BufferUsage::empty()
+            },
+            false,
+            indices,
+        )
+            .unwrap();
+
+        Mesh { vertex_buffer, index_buffer, index_count }
+    }
+
+    // Unit cube centred on the origin, one quad per face so the normals stay flat.
+    pub fn cube(memory_allocator: &StandardMemoryAllocator) -> Self {
+        // (normal, u axis, v axis) for every face
+        let faces: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+            ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+            ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+            ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+            ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+            ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ];
+
+        let mut vertices = Vec::with_capacity(24);
+        let mut indices = Vec::with_capacity(36);
+
+        for (normal, u, v) in faces {
+            let base = vertices.len() as u16;
+
+            for (su, sv) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                let position = [
+                    0.5 * (normal[0] + su * u[0] + sv * v[0]),
+                    0.5 * (normal[1] + su * u[1] + sv * v[1]),
+                    0.5 * (normal[2] + su * u[2] + sv * v[2]),
+                ];
+                let uv = [0.5 * (su + 1.0), 0.5 * (1.0 - sv)];
+                vertices.push(Vertex { position, normal, uv });
+            }
+
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+
+        Mesh::new(memory_allocator, vertices, indices)
+    }
+}
+
+// A node of the transform hierarchy. `local_transform` is relative to the parent,
+// so moving a node moves its whole subtree along with it.
+pub struct Node {
+    pub local_transform: Matrix4<f32>,
+    pub children: Vec<Node>,
+    pub mesh: Option<Mesh>,
+}
+
+impl Node {
+    pub fn new(local_transform: Matrix4<f32>) -> Self {
+        Node {
+            local_transform,
+            children: Vec::new(),
+            mesh: None,
+        }
+    }
+
+    pub fn with_mesh(mut self, mesh: Mesh) -> Self {
+        self.mesh = Some(mesh);
+        self
+    }
+
+    pub fn with_child(mut self, child: Node) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    // Walks down `path` as a list of child indices; an empty path is the node itself.
+    pub fn node_mut(&mut self, path: &[usize]) -> Option<&mut Node> {
+        match path.split_first() {
+            None => Some(self),
+            Some((&index, rest)) => self.children.get_mut(index)?.node_mut(rest),
+        }
+    }
+
+    // Depth-first walk handing every node its world matrix, i.e. the product of
+    // all local transforms from the root down to and including the node.
+    pub fn traverse<'a>(&'a self, parent: &Matrix4<f32>, visit: &mut impl FnMut(Matrix4<f32>, &'a Node)) {
+        let world = parent * self.local_transform;
+        visit(world, self);
+
+        for child in &self.children {
+            child.traverse(&world, visit);
+        }
+    }
+
+    // Every mesh in the tree paired with the world matrix it should be drawn with.
+    pub fn draw_list<'a>(&'a self) -> Vec<(Matrix4<f32>, &'a Mesh)> {
+        let mut list = Vec::new();
+
+        self.traverse(&Matrix4::identity(), &mut |world, node: &'a Node| {
+            if let Some(mesh) = &node.mesh {
+                list.push((world, mesh));
+            }
+        });
+
+        list
+    }
+}
@@ -0,0 +1,130 @@
+#version 450
+
+// A compact take on FXAA 3.11's quality path: find the local edge direction from
+// luma contrast, walk along the edge to both of its ends, and shift the sample
+// position across the edge by how close the nearer end is.
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D ldr;
+
+layout(push_constant) uniform FxaaPush {
+    vec2 inverse_size;
+    // how much sub-pixel aliasing to smooth away, 0 to 1
+    float subpixel;
+    // local contrast below this, relative to the brightest neighbour, is no edge
+    float edge_threshold;
+    // and below this absolute value, so dark areas stay sharp
+    float edge_threshold_min;
+    // texels to walk along an edge in each direction
+    int search_steps;
+} push;
+
+// perceptual enough for edge detection, the input is linear when the format is sRGB
+float luma(vec3 color) {
+    return sqrt(dot(color, vec3(0.299, 0.587, 0.114)));
+}
+
+float luma_at(vec2 uv) {
+    return luma(texture(ldr, uv).rgb);
+}
+
+float luma_offset(vec2 uv, ivec2 offset) {
+    return luma(textureOffset(ldr, uv, offset).rgb);
+}
+
+void main() {
+    vec2 uv = gl_FragCoord.xy * push.inverse_size;
+    vec3 center = texture(ldr, uv).rgb;
+
+    float l_m = luma(center);
+    float l_n = luma_offset(uv, ivec2(0, -1));
+    float l_s = luma_offset(uv, ivec2(0, 1));
+    float l_e = luma_offset(uv, ivec2(1, 0));
+    float l_w = luma_offset(uv, ivec2(-1, 0));
+
+    float l_max = max(l_m, max(max(l_n, l_s), max(l_e, l_w)));
+    float l_min = min(l_m, min(min(l_n, l_s), min(l_e, l_w)));
+    float range = l_max - l_min;
+
+    if (range < max(push.edge_threshold_min, l_max * push.edge_threshold)) {
+        f_color = vec4(center, 1.0);
+        return;
+    }
+
+    float l_ne = luma_offset(uv, ivec2(1, -1));
+    float l_nw = luma_offset(uv, ivec2(-1, -1));
+    float l_se = luma_offset(uv, ivec2(1, 1));
+    float l_sw = luma_offset(uv, ivec2(-1, 1));
+
+    // sub-pixel blend from how far the centre is off its 3x3 neighbourhood
+    float average = (2.0 * (l_n + l_s + l_e + l_w) + l_ne + l_nw + l_se + l_sw) / 12.0;
+    float subpixel = smoothstep(0.0, 1.0, clamp(abs(average - l_m) / range, 0.0, 1.0));
+    subpixel = subpixel * subpixel * push.subpixel;
+
+    float horizontal = 2.0 * abs(l_n + l_s - 2.0 * l_m) + abs(l_ne + l_se - 2.0 * l_e) + abs(l_nw + l_sw - 2.0 * l_w);
+    float vertical = 2.0 * abs(l_e + l_w - 2.0 * l_m) + abs(l_ne + l_nw - 2.0 * l_n) + abs(l_se + l_sw - 2.0 * l_s);
+    bool is_horizontal = horizontal >= vertical;
+
+    // step across the edge towards the side with the steeper gradient
+    float l_positive = is_horizontal ? l_s : l_e;
+    float l_negative = is_horizontal ? l_n : l_w;
+    float step_across = is_horizontal ? push.inverse_size.y : push.inverse_size.x;
+    float l_opposite = l_positive;
+    float gradient = abs(l_positive - l_m);
+    if (abs(l_negative - l_m) > gradient) {
+        step_across = -step_across;
+        l_opposite = l_negative;
+        gradient = abs(l_negative - l_m);
+    }
+
+    // walk along the edge, halfway between this texel and the opposite one
+    vec2 edge_uv = uv;
+    vec2 step_along;
+    if (is_horizontal) {
+        edge_uv.y += 0.5 * step_across;
+        step_along = vec2(push.inverse_size.x, 0.0);
+    } else {
+        edge_uv.x += 0.5 * step_across;
+        step_along = vec2(0.0, push.inverse_size.y);
+    }
+
+    float edge_luma = 0.5 * (l_m + l_opposite);
+    float end_threshold = 0.25 * gradient;
+
+    vec2 uv_positive = edge_uv + step_along;
+    float delta_positive = luma_at(uv_positive) - edge_luma;
+    for (int i = 1; i < push.search_steps && abs(delta_positive) < end_threshold; i++) {
+        uv_positive += step_along;
+        delta_positive = luma_at(uv_positive) - edge_luma;
+    }
+
+    vec2 uv_negative = edge_uv - step_along;
+    float delta_negative = luma_at(uv_negative) - edge_luma;
+    for (int i = 1; i < push.search_steps && abs(delta_negative) < end_threshold; i++) {
+        uv_negative -= step_along;
+        delta_negative = luma_at(uv_negative) - edge_luma;
+    }
+
+    float distance_positive = is_horizontal ? uv_positive.x - uv.x : uv_positive.y - uv.y;
+    float distance_negative = is_horizontal ? uv.x - uv_negative.x : uv.y - uv_negative.y;
+    float distance = min(distance_positive, distance_negative);
+    float delta_end = distance_positive <= distance_negative ? delta_positive : delta_negative;
+
+    // only blend when the nearer end goes the other way from the centre, otherwise
+    // this texel is on the far side of the edge and already right
+    float edge_blend = 0.0;
+    if ((delta_end < 0.0) != (l_m - edge_luma < 0.0)) {
+        edge_blend = 0.5 - distance / (distance_positive + distance_negative);
+    }
+
+    vec2 final_uv = uv;
+    float blend = max(edge_blend, subpixel);
+    if (is_horizontal) {
+        final_uv.y += blend * step_across;
+    } else {
+        final_uv.x += blend * step_across;
+    }
+
+    f_color = vec4(texture(ldr, final_uv).rgb, 1.0);
+}
@@ -0,0 +1,35 @@
+#version 450
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D ldr;
+layout(set = 0, binding = 1) uniform sampler2D velocity;
+
+layout(push_constant) uniform MotionBlurPush {
+    vec2 inverse_size;
+    // fraction of the frame's motion the shutter is open for, 0 turns blur off
+    float strength;
+    int max_samples;
+} push;
+
+void main() {
+    vec2 uv = gl_FragCoord.xy * push.inverse_size;
+    vec2 blur = texture(velocity, uv).xy * push.strength;
+
+    // about one sample per pixel of streak, so slow motion stays cheap; under
+    // two samples there is nothing to blur
+    int samples = min(int(ceil(length(blur / push.inverse_size))), push.max_samples);
+    if (samples < 2) {
+        f_color = vec4(texture(ldr, uv).rgb, 1.0);
+        return;
+    }
+
+    // centred on the pixel, from half a streak behind to half ahead
+    vec3 sum = vec3(0.0);
+    for (int i = 0; i < samples; i++) {
+        float t = float(i) / float(samples - 1) - 0.5;
+        sum += texture(ldr, uv + blur * t).rgb;
+    }
+
+    f_color = vec4(sum / float(samples), 1.0);
+}
@@ -0,0 +1,36 @@
+// Encodes a linear BT.709 colour for the swapchain's colour space. Expects the
+// including shader to declare `globals` with `output_params`:
+// x = 0 sRGB, 1 scRGB, 2 HDR10; y = luminance scale; z = 1 when an sRGB output's
+// format is UNORM and the transfer has to be applied here.
+
+vec3 encode_output(vec3 color) {
+    if (globals.output_params.x == 1.0) {
+        // scRGB is linear with 1.0 at 80 nits, values above 1.0 are brighter
+        return color * globals.output_params.y;
+    }
+
+    if (globals.output_params.x == 2.0) {
+        // BT.709 to BT.2020 primaries, normalised to 10000 nits, then the PQ curve
+        const mat3 bt709_to_bt2020 = mat3(
+            0.6274, 0.0691, 0.0164,
+            0.3293, 0.9195, 0.0880,
+            0.0433, 0.0114, 0.8956
+        );
+        vec3 y = clamp(bt709_to_bt2020 * color * globals.output_params.y, 0.0, 1.0);
+
+        const float m1 = 0.1593017578125;
+        const float m2 = 78.84375;
+        const float c1 = 0.8359375;
+        const float c2 = 18.8515625;
+        const float c3 = 18.6875;
+        vec3 p = pow(y, vec3(m1));
+        return pow((c1 + c2 * p) / (1.0 + c3 * p), vec3(m2));
+    }
+
+    if (globals.output_params.z == 1.0) {
+        vec3 c = clamp(color, 0.0, 1.0);
+        return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, greaterThan(c, vec3(0.0031308)));
+    }
+
+    return color;
+}
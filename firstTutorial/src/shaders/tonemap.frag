@@ -0,0 +1,47 @@
+#version 450
+
+// See the top of tonemap.rs for why there are two ways in.
+#ifdef SAMPLED_INPUT
+layout(set = 0, binding = 0) uniform sampler2D hdr;
+
+vec3 load_hdr() {
+    return texelFetch(hdr, ivec2(gl_FragCoord.xy), 0).rgb;
+}
+#else
+layout(input_attachment_index = 0, set = 0, binding = 0) uniform subpassInput hdr;
+
+vec3 load_hdr() {
+    return subpassLoad(hdr).rgb;
+}
+#endif
+
+layout(location = 0) out vec4 f_color;
+
+// named `globals` for output.glsl
+layout(push_constant) uniform TonemapPush {
+    vec4 output_params;
+    float exposure;
+    // set for debug views, which only get the output encoding
+    int bypass;
+} globals;
+
+#include <output.glsl>
+
+// ACES filmic curve, Krzysztof Narkowicz's fit
+vec3 aces(vec3 x) {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
+void main() {
+    vec3 color = load_hdr();
+
+    // HDR swapchains have headroom above paper white, only SDR needs the curve
+    if (globals.bypass == 0) {
+        color *= globals.exposure;
+        if (globals.output_params.x == 0.0) {
+            color = aces(color);
+        }
+    }
+
+    f_color = vec4(encode_output(color), 1.0);
+}
@@ -0,0 +1,124 @@
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, PrimaryCommandBufferAbstract};
+use vulkano::device::{DeviceOwned, Queue};
+use vulkano::image::view::ImageView;
+use vulkano::image::{ImageAccess, ImmutableImage};
+use vulkano::memory::allocator::StandardMemoryAllocator;
+use vulkano::sync::GpuFuture;
+
+use std::io;
+use std::mem;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::cache::ResourceCache;
+use crate::{debug, volume};
+
+// With the cache generation it was loaded under: one from an older generation than
+// what's already in use is out of date.
+pub enum StreamedAsset {
+    Volume(Arc<ImageView<ImmutableImage>>, u64),
+}
+
+// Loads assets on worker threads and uploads them through `queue`, normally a
+// dedicated transfer queue. Finished assets are only sent once their upload fence
+// has signalled, so whatever `poll` returns is ready to draw.
+//
+// Sharing between the transfer and graphics families is concurrent. vulkano creates
+// uploaded images with every active queue family and no ownership to hand over.
+// Exclusive ownership would be faster on some hardware: the driver can keep
+// compression and other family-specific layouts it has to give up for concurrent
+// images. But that needs a release barrier on this queue and a matching acquire on
+// the graphics queue, and vulkano 0.32's automatic synchronisation has no way to
+// record either. Revisit once it exposes queue family ownership transfers.
+pub struct AssetStreamer {
+    queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    cache: Arc<ResourceCache>,
+    fence_timeout: Duration,
+    sender: Sender<StreamedAsset>,
+    receiver: Receiver<StreamedAsset>,
+}
+
+impl AssetStreamer {
+    pub fn new(
+        queue: Arc<Queue>,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        cache: Arc<ResourceCache>,
+        fence_timeout: Duration,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        AssetStreamer {
+            queue,
+            memory_allocator,
+            cache,
+            fence_timeout,
+            sender,
+            receiver,
+        }
+    }
+
+    pub fn load_volume(&self, path: PathBuf, dimensions: [u32; 3]) {
+        self.spawn_volume_load(path, dimensions, false);
+    }
+
+    // Loads the volume again after its file changed, replacing the cached one.
+    pub fn reload_volume(&self, path: PathBuf, dimensions: [u32; 3]) {
+        self.spawn_volume_load(path, dimensions, true);
+    }
+
+    fn spawn_volume_load(&self, path: PathBuf, dimensions: [u32; 3], reload: bool) {
+        let queue = self.queue.clone();
+        let memory_allocator = self.memory_allocator.clone();
+        let cache = self.cache.clone();
+        let fence_timeout = self.fence_timeout;
+        let sender = self.sender.clone();
+
+        thread::spawn(move || {
+            let load = || {
+                let data = volume::load_raw(&path, dimensions)?;
+
+                let command_buffer_allocator = StandardCommandBufferAllocator::new(queue.device().clone(), Default::default());
+                let mut uploads = AutoCommandBufferBuilder::primary(
+                    &command_buffer_allocator,
+                    queue.queue_family_index(),
+                    CommandBufferUsage::OneTimeSubmit,
+                ).unwrap();
+                let texture = volume::create_texture(&memory_allocator, data, dimensions, &mut uploads);
+                debug::set_name(&**texture.image().inner().image, "volume texture");
+
+                let future = uploads.build().unwrap()
+                    .execute(queue.clone())
+                    .unwrap()
+                    .then_signal_fence_and_flush()
+                    .unwrap();
+                if let Err(e) = future.wait(Some(fence_timeout)) {
+                    // dropping the future waits for the fence again, with no timeout
+                    mem::forget(future);
+                    return Err(io::Error::new(io::ErrorKind::Other, format!("upload did not finish: {:?}", e)));
+                }
+
+                Ok::<_, io::Error>(texture)
+            };
+            let result = if reload { cache.reload_texture(&path, load) } else { cache.texture(&path, load) };
+
+            match result {
+                Ok(Some(loaded)) => {
+                    // the receiving side only goes away when the app is shutting down
+                    let _ = sender.send(StreamedAsset::Volume(loaded.value, loaded.generation));
+                }
+                Ok(None) => debug::log(format_args!("dropped a load of {} overtaken by a later reload", path.display())),
+                Err(e) => eprintln!("Failed to load volume {}: {}", path.display(), e),
+            }
+        });
+    }
+
+    // Everything that finished uploading since the last call, without blocking.
+    pub fn poll(&self) -> impl Iterator<Item = StreamedAsset> + '_ {
+        self.receiver.try_iter()
+    }
+}
@@ -0,0 +1,136 @@
+// Tonemapping runs as the second subpass of the main render pass. It reads the HDR
+// scene colour with `subpassLoad` from an input attachment, which only ever gives the
+// fragment its own pixel. In exchange, tiled GPUs can keep the HDR target in tile
+// memory for the whole pass and never write it out (it is `store: DontCare`).
+//
+// That makes a subpass the right fit for per-pixel operations like tonemapping,
+// exposure, colour grading and output encoding. Anything that needs neighbouring
+// pixels (blurs, bloom, FXAA) can't be a subpass input: the HDR target then has to be
+// stored, and the effect becomes its own render pass that samples it as a texture.
+//
+// Dynamic rendering has no subpasses, so on that path the HDR target is stored and
+// sampled by a second `begin_rendering` scope instead, giving up the on-tile win.
+
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::render_pass::PipelineRenderPassType;
+use vulkano::pipeline::graphics::vertex_input::VertexInputState;
+use vulkano::pipeline::graphics::viewport::ViewportState;
+use vulkano::pipeline::GraphicsPipeline;
+
+use std::sync::Arc;
+
+use crate::sampler::SamplerKey;
+
+// Format of the intermediate scene colour the first subpass renders into.
+pub const HDR_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+// For `sampled_fs`, which only ever fetches texels, so the filter doesn't matter.
+pub const SAMPLER: SamplerKey = SamplerKey::linear_clamp();
+
+// Full-screen triangle generated in the vertex shader, no vertex buffer needed.
+// `sampled_input` picks `sampled_fs` over the subpass input version.
+pub fn create_pipeline(device: Arc<Device>, render_pass: PipelineRenderPassType, sampled_input: bool) -> Arc<GraphicsPipeline> {
+    let vs = vs::load(device.clone()).unwrap();
+    let fs = if sampled_input {
+        sampled_fs::load(device.clone()).unwrap()
+    } else {
+        fs::load(device.clone()).unwrap()
+    };
+
+    GraphicsPipeline::start()
+        .vertex_input_state(VertexInputState::new())
+        .vertex_shader(vs.entry_point("main").unwrap(), ())
+        .input_assembly_state(InputAssemblyState::new())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_dynamic(1))
+        .fragment_shader(fs.entry_point("main").unwrap(), ())
+        .render_pass(render_pass)
+        .build(device)
+        .unwrap()
+}
+
+pub mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+            #version 450
+
+            void main() {
+                vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+                gl_Position = vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+            }
+        "
+    }
+}
+
+// Reads the HDR colour as a subpass input.
+pub mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/shaders/tonemap.frag",
+        include: ["src/shaders"],
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
+    }
+}
+
+// Same shader reading the HDR colour from a sampled image, for dynamic rendering.
+// Its push constants match `fs::ty::TonemapPush`.
+pub mod sampled_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/shaders/tonemap.frag",
+        include: ["src/shaders"],
+        define: [("SAMPLED_INPUT", "1")],
+    }
+}
+
+// The resolve order on one pixel of a bright edge, with the shaders' curve and
+// encoding copied out: the scene resolves its MSAA samples in linear HDR and only
+// then tonemaps and encodes, which is what this checks against the other way round.
+#[cfg(test)]
+mod tests {
+    // `aces` in tonemap.frag
+    fn aces(x: f32) -> f32 {
+        ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)
+    }
+
+    // the sRGB branch of `encode_output` in output.glsl
+    fn encode_srgb(c: f32) -> f32 {
+        let c = c.clamp(0.0, 1.0);
+        if c > 0.0031308 {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        } else {
+            c * 12.92
+        }
+    }
+
+    fn average(samples: &[f32]) -> f32 {
+        samples.iter().sum::<f32>() / samples.len() as f32
+    }
+
+    #[test]
+    fn bright_edges_resolve_before_encoding() {
+        // 4x MSAA, half the samples on a light at 8x paper white, half on black
+        let samples = [8.0, 8.0, 0.0, 0.0];
+
+        let resolve_then_encode = encode_srgb(aces(average(&samples)));
+        let encode_then_resolve = average(&samples.map(|sample| encode_srgb(aces(sample))));
+
+        // averaging encoded values darkens the edge to about half of full white,
+        // resolving first keeps half the light's energy, which the curve still maps
+        // close to white
+        assert!(
+            (resolve_then_encode - encode_then_resolve).abs() > 0.25,
+            "{} and {} should differ",
+            resolve_then_encode,
+            encode_then_resolve,
+        );
+        assert!(resolve_then_encode > 0.9, "the edge came out at {}", resolve_then_encode);
+        assert!(encode_then_resolve < 0.55, "the edge came out at {}", encode_then_resolve);
+    }
+}
@@ -0,0 +1,50 @@
+// Motion vectors: the scene pass writes every fragment's screen-space motion since
+// the previous frame into a second colour attachment, for effects that need to know
+// where a pixel was (motion blur, temporal antialiasing).
+//
+// A texel holds the current minus the previous position in UV units of the whole
+// target, so sampling at `uv - velocity` finds where the surface was the frame
+// before. In split screen that's scaled down from the view's own UV, so every view
+// shares the units the post passes sample in. What the scene is
+// cleared to, and what nothing has been drawn over, counts as not moving.
+
+use cgmath::Matrix4;
+use vulkano::format::Format;
+
+use crate::scene::Mesh;
+
+pub const FORMAT: Format = Format::R16G16_SFLOAT;
+
+pub const CLEAR_VALUE: [f32; 4] = [0.0; 4];
+
+// Last frame's transforms. Meshes are matched up by their position in the draw list
+// and views by their index, so when either count changes they start over with no
+// motion rather than pairing up the wrong ones.
+#[derive(Default)]
+pub struct MotionHistory {
+    worlds: Vec<Matrix4<f32>>,
+    view_projs: Vec<Matrix4<f32>>,
+}
+
+impl MotionHistory {
+    // `draw_list` with every mesh's world matrix from the previous frame added.
+    pub fn with_previous<'a>(&self, draw_list: &[(Matrix4<f32>, &'a Mesh)]) -> Vec<(Matrix4<f32>, Matrix4<f32>, &'a Mesh)> {
+        let known = self.worlds.len() == draw_list.len();
+
+        draw_list
+            .iter()
+            .enumerate()
+            .map(|(i, &(world, mesh))| (world, if known { self.worlds[i] } else { world }, mesh))
+            .collect()
+    }
+
+    pub fn previous_view_proj(&self, view: usize, current: Matrix4<f32>) -> Matrix4<f32> {
+        self.view_projs.get(view).copied().unwrap_or(current)
+    }
+
+    // Remembers this frame's transforms for the next one.
+    pub fn store(&mut self, draw_list: &[(Matrix4<f32>, &Mesh)], view_projs: Vec<Matrix4<f32>>) {
+        self.worlds = draw_list.iter().map(|&(world, _)| world).collect();
+        self.view_projs = view_projs;
+    }
+}
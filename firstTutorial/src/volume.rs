@@ -0,0 +1,234 @@
+use vulkano::command_buffer::allocator::CommandBufferAllocator;
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::{ImageDimensions, ImmutableImage, MipmapsCount, SampleCount};
+use vulkano::memory::allocator::StandardMemoryAllocator;
+use vulkano::pipeline::graphics::color_blend::{ColorBlendState, ColorComponents};
+use vulkano::pipeline::graphics::depth_stencil::{CompareOp, DepthState, DepthStencilState};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::{CullMode, RasterizationState};
+use vulkano::pipeline::graphics::render_pass::PipelineRenderPassType;
+use vulkano::pipeline::graphics::vertex_input::BuffersDefinition;
+use vulkano::pipeline::graphics::viewport::ViewportState;
+use vulkano::pipeline::{GraphicsPipeline, StateMode};
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::sampler::SamplerKey;
+use crate::scene::Vertex;
+
+// Ray-marching parameters, pushed to the fragment shader every frame.
+pub struct VolumeSettings {
+    pub steps: u32,
+    // densities below `low` are fully transparent, above `high` fully opaque
+    pub low: f32,
+    pub high: f32,
+    // opacity per unit length of the bounding cube
+    pub opacity: f32,
+}
+
+impl Default for VolumeSettings {
+    fn default() -> Self {
+        VolumeSettings {
+            steps: 128,
+            low: 0.2,
+            high: 0.8,
+            opacity: 8.0,
+        }
+    }
+}
+
+impl VolumeSettings {
+    pub fn transfer(&self) -> [f32; 4] {
+        [self.low, self.high, self.opacity, 0.0]
+    }
+}
+
+// Reads a headerless file of `width * height * depth` 8-bit densities, x varying fastest.
+pub fn load_raw(path: &Path, dimensions: [u32; 3]) -> io::Result<Vec<u8>> {
+    let data = std::fs::read(path)?;
+    let expected = dimensions.iter().map(|&d| d as usize).product::<usize>();
+
+    if data.len() != expected {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "expected {} bytes for a {}x{}x{} volume, found {}",
+                expected, dimensions[0], dimensions[1], dimensions[2], data.len(),
+            ),
+        ));
+    }
+
+    Ok(data)
+}
+
+// Records the upload of `data` into a single-channel 3D image on `builder`.
+pub fn create_texture<L, A>(
+    memory_allocator: &StandardMemoryAllocator,
+    data: Vec<u8>,
+    dimensions: [u32; 3],
+    builder: &mut AutoCommandBufferBuilder<L, A>,
+) -> Arc<ImageView<ImmutableImage>>
+where
+    A: CommandBufferAllocator,
+{
+    let image = ImmutableImage::from_iter(
+        memory_allocator,
+        data,
+        ImageDimensions::Dim3d {
+            width: dimensions[0],
+            height: dimensions[1],
+            depth: dimensions[2],
+        },
+        MipmapsCount::One,
+        Format::R8_UNORM,
+        builder,
+    )
+        .unwrap();
+
+    ImageView::new_default(image).unwrap()
+}
+
+// Trilinear without wrapping, so samples at the cube's faces don't bleed across.
+pub const SAMPLER: SamplerKey = SamplerKey::linear_clamp();
+
+// Draws the back faces of the bounding cube and marches from the eye through the
+// volume in the fragment shader. Back faces keep working when the camera is inside
+// the cube; depth is tested but not written so opaque geometry in front still wins.
+// `depth_compare` is the test that keeps closer fragments, see `DepthMode::closer`.
+pub fn create_pipeline(device: Arc<Device>, render_pass: PipelineRenderPassType, samples: SampleCount, depth_compare: CompareOp) -> Arc<GraphicsPipeline> {
+    let vs = vs::load(device.clone()).unwrap();
+    let fs = fs::load(device.clone()).unwrap();
+
+    // the velocity attachment keeps the motion of what's behind
+    let mut color_blend_state = ColorBlendState::new(2).blend_alpha();
+    color_blend_state.attachments[1].color_write_mask = ColorComponents::empty();
+
+    GraphicsPipeline::start()
+        .vertex_input_state(BuffersDefinition::new().vertex::<Vertex>())
+        .vertex_shader(vs.entry_point("main").unwrap(), ())
+        .input_assembly_state(InputAssemblyState::new())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_dynamic(1))
+        .fragment_shader(fs.entry_point("main").unwrap(), ())
+        .rasterization_state(RasterizationState::new().cull_mode(CullMode::Front))
+        .depth_stencil_state(DepthStencilState {
+            depth: Some(DepthState {
+                enable_dynamic: false,
+                write_enable: StateMode::Fixed(false),
+                compare_op: StateMode::Fixed(depth_compare),
+            }),
+            ..DepthStencilState::disabled()
+        })
+        .color_blend_state(color_blend_state)
+        .multisample_state(MultisampleState {
+            rasterization_samples: samples,
+            ..Default::default()
+        })
+        .render_pass(render_pass)
+        .build(device)
+        .unwrap()
+}
+
+pub mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+            #version 450
+
+            layout(location = 0) in vec3 position;
+
+            layout(location = 0) out vec3 v_object;
+
+            layout(set = 0, binding = 0) uniform Globals {
+                mat4 view;
+                mat4 proj;
+            } globals;
+
+            layout(push_constant) uniform VolumePush {
+                mat4 model;
+                vec4 eye_object;
+                vec4 transfer;
+                int steps;
+            } push;
+
+            void main() {
+                v_object = position;
+                gl_Position = globals.proj * globals.view * push.model * vec4(position, 1.0);
+            }
+        ",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
+    }
+}
+
+pub mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 450
+
+            // position on the bounding cube, in the cube's own [-0.5, 0.5] space
+            layout(location = 0) in vec3 v_object;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 1) uniform sampler3D volume;
+
+            layout(push_constant) uniform VolumePush {
+                mat4 model;
+                vec4 eye_object;
+                // x: low density, y: high density, z: opacity per unit length
+                vec4 transfer;
+                int steps;
+            } push;
+
+            vec4 transfer_function(float density) {
+                float t = clamp((density - push.transfer.x) / max(push.transfer.y - push.transfer.x, 1e-4), 0.0, 1.0);
+                vec3 color = mix(vec3(0.1, 0.3, 1.0), vec3(1.0, 0.9, 0.6), t);
+                return vec4(color, t * push.transfer.z);
+            }
+
+            void main() {
+                vec3 origin = push.eye_object.xyz;
+                vec3 direction = normalize(v_object - origin);
+
+                // slab test against the cube, clamped to start at the eye when inside
+                vec3 t0 = (vec3(-0.5) - origin) / direction;
+                vec3 t1 = (vec3(0.5) - origin) / direction;
+                vec3 t_near = min(t0, t1);
+                vec3 t_far = max(t0, t1);
+                float t_enter = max(max(max(t_near.x, t_near.y), t_near.z), 0.0);
+                float t_exit = min(min(t_far.x, t_far.y), t_far.z);
+
+                int steps = max(push.steps, 1);
+                float step_size = max(t_exit - t_enter, 0.0) / float(steps);
+
+                // front-to-back compositing
+                vec4 accum = vec4(0.0);
+                for (int i = 0; i < steps && accum.a < 0.99; i++) {
+                    vec3 p = origin + direction * (t_enter + (float(i) + 0.5) * step_size);
+                    vec4 s = transfer_function(texture(volume, p + 0.5).r);
+                    float alpha = 1.0 - exp(-s.a * step_size);
+
+                    accum.rgb += (1.0 - accum.a) * alpha * s.rgb;
+                    accum.a += (1.0 - accum.a) * alpha;
+                }
+
+                f_color = vec4(accum.rgb / max(accum.a, 1e-4), accum.a);
+            }
+        ",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        },
+    }
+}